@@ -0,0 +1,129 @@
+#[cfg(unix)]
+use std::{sync::Mutex, time::Instant};
+use {
+    crate::*,
+    std::{sync::OnceLock, time::Duration},
+};
+
+/// The env variable through which a parent process can give the
+/// background it detected to its children (see [export_cache])
+pub const CACHE_VAR: &str = "TERMINAL_LIGHT_CACHE";
+
+static LUMA: OnceLock<Option<f32>> = OnceLock::new();
+
+#[cfg(unix)]
+static LUMA_TTL: Mutex<Option<(Instant, Option<f32>)>> = Mutex::new(None);
+
+/// Return the luma of the terminal's background, querying it only
+/// on the first call: next calls return the same value for the whole
+/// life of the process.
+///
+/// Return `None` when the luma couldn't be determined (call [luma]
+/// if you want the error).
+pub fn cached_luma() -> Option<f32> {
+    *LUMA.get_or_init(|| luma().ok())
+}
+
+/// Start determining the luma of the terminal's background on a
+/// detached thread, to fill the cache of [cached_luma].
+///
+/// Call it at the start of the program, so that the probe overlaps
+/// with the rest of the initialization: a later call of [cached_luma]
+/// returns immediately if the probe is done, and otherwise waits for
+/// its end.
+///
+/// As the terminal's answer comes on stdin, the application shouldn't
+/// read stdin until [cached_luma] has returned.
+///
+/// When no thread can be started (eg on wasm), this does nothing and
+/// the probe is done by the first call of [cached_luma].
+pub fn prewarm() {
+    let _ = std::thread::Builder::new().spawn(|| {
+        cached_luma();
+    });
+}
+
+/// Return the luma of the terminal's background, querying it only
+/// if the last known value is older than the given TTL.
+///
+/// This cache is independent from the one of [cached_luma], and suited
+/// to applications which poll the luma to follow a theme change
+/// of the terminal. On non unix platforms, where the terminal isn't
+/// queried, nothing is cached.
+///
+/// Return `None` when the luma couldn't be determined (call [luma]
+/// if you want the error).
+pub fn cached_luma_ttl(ttl: Duration) -> Option<f32> {
+    #[cfg(unix)]
+    {
+        let mut cache = LUMA_TTL.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((time, luma)) = *cache {
+            if time.elapsed() < ttl {
+                return luma;
+            }
+        }
+        let luma = luma().ok();
+        *cache = Some((Instant::now(), luma));
+        luma
+    }
+    // there's no query to save on other platforms (and no clock on wasm)
+    #[cfg(not(unix))]
+    {
+        let _ = ttl;
+        luma().ok()
+    }
+}
+
+/// Detect the terminal's background and return it in the format of
+/// the [CACHE_VAR] env variable, for a parent process (a shell, a
+/// launcher) to probe once and give the answer to all its children.
+///
+/// The value is the background in the X11 format of the OSC answers
+/// (eg `rgb:2828/2c2c/3434`), or an empty string when it couldn't be
+/// determined. As the children read it before trying any other strategy
+/// and without querying anything, [background_color] and the functions
+/// based on it become instant there.
+///
+/// ```
+/// let mut child = std::process::Command::new("ls");
+/// child.env(terminal_light::CACHE_VAR, terminal_light::export_cache());
+/// ```
+///
+/// Beware that
+/// * the value isn't refreshed: if the terminal's theme changes, the
+///   children still see the background of the time of the export
+///   (except in a [watch], which always queries the terminal),
+/// * the variable is inherited by all descendants, including the ones
+///   started later in another terminal (eg a tmux server) or over ssh
+///   if it's forwarded, so don't export it from long running processes,
+/// * anybody able to set the environment decides the detected background;
+///   it's only a color, but don't trust it for anything else than display.
+pub fn export_cache() -> String {
+    background_rgb()
+        .map(|rgb| {
+            format!(
+                "rgb:{:02x}{0:02x}/{:02x}{1:02x}/{:02x}{2:02x}",
+                rgb.r, rgb.g, rgb.b
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Return the background given by a parent process in the [CACHE_VAR]
+/// env variable, if any (an empty or invalid value is ignored)
+pub(crate) fn exported_background() -> Option<Rgb> {
+    let value = env::var(CACHE_VAR)?;
+    reply::parse_rgba(value.trim()).ok().map(|(rgb, _)| rgb)
+}
+
+#[test]
+fn test_exported_background() {
+    env::with_env(&[(CACHE_VAR, Some("rgb:2828/2c2c/3434"))], || {
+        assert_eq!(exported_background(), Some(Rgb::new(0x28, 0x2c, 0x34)));
+        assert_eq!(background_rgb().unwrap(), Rgb::new(0x28, 0x2c, 0x34));
+        assert_eq!(export_cache(), "rgb:2828/2c2c/3434");
+    });
+    env::with_env(&[(CACHE_VAR, Some(""))], || {
+        assert_eq!(exported_background(), None);
+    });
+}
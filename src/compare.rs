@@ -0,0 +1,78 @@
+use crate::*;
+
+/// Comparison helpers on colors, mainly useful to detect
+/// a change of background between two detections.
+///
+/// Colors are always compared in RGB: an ANSI color is first converted
+/// to RGB (using the standard xterm palette), so that an ANSI result coming
+/// from `$COLORFGBG` can be compared with an RGB result coming from the
+/// terminal.
+pub trait ColorCompare {
+    /// Tell whether the two colors are equal, up to the given tolerance
+    /// on each RGB channel (0 means strictly equal, 255 means any colors
+    /// are considered equal).
+    fn approx_eq(self, other: Self, tolerance: u8) -> bool;
+
+    /// Return the absolute difference of luma between the two colors,
+    /// from 0 (same light) to 1 (black vs white).
+    fn luma_delta(self, other: Self) -> f32;
+
+    /// Return the WCAG contrast ratio between the two colors, from
+    /// 1 (no contrast) to 21 (black vs white).
+    ///
+    /// Reference: <https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio>
+    fn contrast_ratio(self, other: Self) -> f32;
+}
+
+/// Compute the WCAG relative luminance of a color, from 0 to 1
+fn relative_luminance(rgb: Rgb) -> f32 {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(rgb.r) + 0.7152 * linear(rgb.g) + 0.0722 * linear(rgb.b)
+}
+
+impl ColorCompare for Color {
+    fn approx_eq(self, other: Self, tolerance: u8) -> bool {
+        let (a, b) = (self.rgb(), other.rgb());
+        a.r.abs_diff(b.r) <= tolerance
+            && a.g.abs_diff(b.g) <= tolerance
+            && a.b.abs_diff(b.b) <= tolerance
+    }
+    fn luma_delta(self, other: Self) -> f32 {
+        (self.luma() - other.luma()).abs()
+    }
+    fn contrast_ratio(self, other: Self) -> f32 {
+        let a = relative_luminance(self.rgb());
+        let b = relative_luminance(other.rgb());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+}
+
+#[test]
+fn test_approx_eq() {
+    let black = Color::Rgb(Rgb::new(0, 0, 0));
+    let near_black = Color::Rgb(Rgb::new(3, 0, 2));
+    assert!(black.approx_eq(black, 0));
+    assert!(!black.approx_eq(near_black, 2));
+    assert!(black.approx_eq(near_black, 3));
+    // ANSI 15 is white in the standard palette
+    let white = Color::Rgb(Rgb::new(255, 255, 255));
+    assert!(Color::Ansi(AnsiColor::new(15)).approx_eq(white, 0));
+    assert!(black.luma_delta(white) > 0.9);
+    assert_eq!(white.luma_delta(Color::Ansi(AnsiColor::new(15))), 0.0);
+}
+
+#[test]
+fn test_contrast_ratio() {
+    let black = Color::Rgb(Rgb::new(0, 0, 0));
+    let white = Color::Rgb(Rgb::new(255, 255, 255));
+    assert!((black.contrast_ratio(white) - 21.0).abs() < 0.01);
+    assert!((white.contrast_ratio(black) - 21.0).abs() < 0.01);
+    assert!((white.contrast_ratio(white) - 1.0).abs() < 0.01);
+}
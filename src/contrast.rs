@@ -0,0 +1,72 @@
+use crate::*;
+
+const BLACK: Rgb = Rgb::new(0, 0, 0);
+const WHITE: Rgb = Rgb::new(255, 255, 255);
+
+/// Return the color, black or white, which is the most readable
+/// over the given background
+fn readable_over(bg: Color) -> Rgb {
+    if Color::Rgb(BLACK).contrast_ratio(bg) > Color::Rgb(WHITE).contrast_ratio(bg) {
+        BLACK
+    } else {
+        WHITE
+    }
+}
+
+/// Return the text color, black or white, with the highest
+/// contrast over the terminal's background.
+///
+/// If you'd rather have a color than an error when the background
+/// can't be determined, white is the usual safe choice, as most
+/// terminals are dark:
+///
+/// ```
+/// let text_color = terminal_light::readable_text_color()
+///     .unwrap_or(terminal_light::Rgb::new(255, 255, 255));
+/// ```
+pub fn readable_text_color() -> Result<Rgb, TlError> {
+    background_color().map(readable_over)
+}
+
+/// Return the candidate which has the highest contrast over the
+/// terminal's background.
+///
+/// If there's no candidate, the most readable of black and white
+/// is returned (see [readable_text_color]).
+pub fn best_contrast(candidates: &[Color]) -> Result<Color, TlError> {
+    let bg = background_color()?;
+    let best = candidates
+        .iter()
+        .copied()
+        .map(|c| (c, c.contrast_ratio(bg)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(c, _)| c);
+    Ok(best.unwrap_or_else(|| Color::Rgb(readable_over(bg))))
+}
+
+/// Return how far the background a theme assumes is from the terminal's
+/// background, as their difference of luma, from 0 (perfect fit) to 1
+/// (black vs white).
+///
+/// This lets you rank candidate themes, the best fitting one having the
+/// lowest score:
+///
+/// ```
+/// use terminal_light::{theme_fit, Color, Rgb};
+///
+/// let themes = [("dark", Rgb::new(30, 30, 40)), ("light", Rgb::new(250, 250, 240))];
+/// let best = themes
+///     .iter()
+///     .filter_map(|&(name, bg)| theme_fit(Color::Rgb(bg)).ok().map(|fit| (name, fit)))
+///     .min_by(|(_, a), (_, b)| a.total_cmp(b));
+/// ```
+pub fn theme_fit(theme_bg: Color) -> Result<f32, TlError> {
+    background_color().map(|bg| bg.luma_delta(theme_bg))
+}
+
+#[test]
+fn test_readable_over() {
+    assert_eq!(readable_over(Color::Rgb(Rgb::new(20, 20, 30))), WHITE);
+    assert_eq!(readable_over(Color::Rgb(Rgb::new(240, 240, 220))), BLACK);
+    assert_eq!(readable_over(Color::Ansi(AnsiColor::new(15))), BLACK);
+}
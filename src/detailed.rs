@@ -0,0 +1,67 @@
+use crate::*;
+
+/// A color given both as RGB, for precise rendering, and as the nearest
+/// of the 16 standard ANSI colors, for a 16 colors fallback
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetailedColor {
+    pub rgb: Rgb,
+    /// The ANSI code, from 0 to 15
+    pub ansi16: u8,
+}
+
+impl DetailedColor {
+    /// Describe a color, using the given palette of the 16 standard ANSI
+    /// colors, or the standard xterm one when not given.
+    ///
+    /// An ANSI color from 0 to 15 keeps its code, other colors get the
+    /// code of the nearest ANSI color.
+    pub fn new(color: Color, palette: Option<&[Rgb; 16]>) -> Self {
+        match color {
+            Color::Ansi(ansi) if ansi.code < 16 => Self {
+                rgb: palette.map_or_else(|| ansi.to_rgb(), |palette| palette[ansi.code as usize]),
+                ansi16: ansi.code,
+            },
+            _ => {
+                let rgb = color.rgb();
+                Self {
+                    rgb,
+                    ansi16: nearest_ansi16(rgb, palette),
+                }
+            }
+        }
+    }
+}
+
+/// Return the code of the color of the palette nearest to the given one
+fn nearest_ansi16(rgb: Rgb, palette: Option<&[Rgb; 16]>) -> u8 {
+    match palette {
+        Some(palette) => (0..16)
+            .min_by(|&a, &b| {
+                rgb.distance_to(palette[a as usize])
+                    .total_cmp(&rgb.distance_to(palette[b as usize]))
+            })
+            .unwrap_or(0),
+        None => rgb.nearest_ansi_in_range(0, 15).code,
+    }
+}
+
+#[test]
+fn test_detailed_color() {
+    let navy = Color::Ansi(AnsiColor::new(4));
+    assert_eq!(
+        DetailedColor::new(navy, None),
+        DetailedColor {
+            rgb: AnsiColor::new(4).to_rgb(),
+            ansi16: 4,
+        },
+    );
+    let near_black = DetailedColor::new(Color::Rgb(Rgb::new(10, 8, 12)), None);
+    assert_eq!(near_black.ansi16, 0);
+    assert_eq!(near_black.rgb, Rgb::new(10, 8, 12));
+    // with a custom palette
+    let mut palette = [Rgb::new(255, 255, 255); 16];
+    palette[4] = Rgb::new(0x1e, 0x1e, 0x2e);
+    assert_eq!(DetailedColor::new(navy, Some(&palette)).rgb, palette[4]);
+    let dark = DetailedColor::new(Color::Rgb(Rgb::new(0x20, 0x20, 0x30)), Some(&palette));
+    assert_eq!(dark.ansi16, 4);
+}
@@ -1,42 +1,362 @@
 use crate::*;
 
+#[cfg(any(test, feature = "testing"))]
+use std::{cell::RefCell, collections::HashMap};
+
+#[cfg(any(test, feature = "testing"))]
+thread_local! {
+    static OVERRIDES: RefCell<Option<HashMap<String, Option<String>>>> = const { RefCell::new(None) };
+}
+
+/// Read an env variable, returning `None` when it's not set
+/// (or not valid unicode).
+///
+/// All the env variables used by terminal-light are read with this
+/// function, so that tests may override them (see `with_env`).
+pub(crate) fn var(name: &str) -> Option<String> {
+    #[cfg(any(test, feature = "testing"))]
+    {
+        let overridden = OVERRIDES.with(|overrides| {
+            overrides
+                .borrow()
+                .as_ref()
+                .and_then(|overrides| overrides.get(name).cloned())
+        });
+        if let Some(value) = overridden {
+            return value;
+        }
+    }
+    std::env::var(name).ok()
+}
+
+/// Run the given function with some env variables overridden,
+/// as seen by terminal-light (the real process environment isn't
+/// modified).
+///
+/// A `None` value makes the variable appear as not set. Variables
+/// which aren't in the overrides are read from the real environment.
+///
+/// The overrides only apply to the current thread, so that tests
+/// running in parallel don't see the overrides of the other tests.
+///
+/// This function is only available with the `testing` feature.
+#[cfg(any(test, feature = "testing"))]
+pub fn with_env<R, F>(overrides: &[(&str, Option<&str>)], f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    /// Restore the previous overrides, even on panic
+    struct Restore(Option<HashMap<String, Option<String>>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            OVERRIDES.with(|overrides| *overrides.borrow_mut() = previous);
+        }
+    }
+    let overrides = overrides
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value.map(str::to_string)))
+        .collect();
+    let _restore = Restore(OVERRIDES.with(|o| o.replace(Some(overrides))));
+    f()
+}
+
+/// The content of the $COLORFGBG env variable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorFgBg {
+    /// The foreground, `None` when it's the terminal's default one
+    pub foreground: Option<AnsiColor>,
+    /// The background, `None` when it's the terminal's default one
+    pub background: Option<AnsiColor>,
+    /// Whether there was a middle `default` field, as in `0;default;15`
+    pub had_default_field: bool,
+}
+
+/// Query the $COLORFGBG env variable and parse it
+pub fn color_fg_bg() -> Result<ColorFgBg, TlError> {
+    let s = var("COLORFGBG").ok_or(TlError::NoColorFgBgEnv)?;
+    parse_colorfgbg(&s)
+}
+
 /// Query the $COLORFGBG env variable and parse
 /// the result to extract the background in ANSI.
-#[allow(clippy::iter_skip_next)]
 pub fn bg_color() -> Result<AnsiColor, TlError> {
-    let s = std::env::var("COLORFGBG").map_err(|_| TlError::NoColorFgBgEnv)?;
-    parse_colorfgbg(&s)
+    color_fg_bg()?.background.ok_or(TlError::DefaultBackground)
+}
+
+/// Query the $COLORFGBG env variable and parse
+/// the result to extract the foreground in ANSI.
+pub fn fg_color() -> Result<AnsiColor, TlError> {
+    color_fg_bg()?.foreground.ok_or(TlError::DefaultForeground)
+}
+
+/// Name returned by [term_program] when running in an Emacs terminal
+pub const EMACS: &str = "emacs";
+
+/// Name returned by [term_program] when running in macOS's Terminal.app
+pub const APPLE_TERMINAL: &str = "Apple_Terminal";
+
+/// Return the name of the terminal program we're running in, when known.
+///
+/// This is [EMACS] when running in an Emacs terminal (`ansi-term`, `vterm`,
+/// etc.), as told by the `$INSIDE_EMACS` env variable, and else the value of
+/// the `$TERM_PROGRAM` env variable (eg `"iTerm.app"` or `"WezTerm"`).
+pub fn term_program() -> Option<String> {
+    if var("INSIDE_EMACS").is_some() {
+        return Some(EMACS.to_string());
+    }
+    var("TERM_PROGRAM")
+}
+
+/// Env variables set by CI providers (GitHub Actions, GitLab CI, etc.)
+/// in addition to or instead of `$CI`
+const CI_VARS: &[&str] = &[
+    "GITHUB_ACTIONS",
+    "GITLAB_CI",
+    "BUILDKITE",
+    "CIRCLECI",
+    "TRAVIS",
+    "JENKINS_URL",
+    "TF_BUILD",
+    "TEAMCITY_VERSION",
+];
+
+/// Tell whether we're running under a CI provider, as told by the `$CI`
+/// env variable (unless it's `false` or `0`) or provider specific ones
+pub fn is_ci() -> bool {
+    if var("CI").is_some_and(|ci| ci != "false" && ci != "0") {
+        return true;
+    }
+    CI_VARS.iter().any(|&name| var(name).is_some())
+}
+
+/// Tell whether the color control conventions ask for no color, that is
+/// whether `$NO_COLOR` is set to a non empty value, or `$CLICOLOR` is `0`
+/// without `$CLICOLOR_FORCE` asking for colors.
+///
+/// `$NO_COLOR` has precedence over `$CLICOLOR_FORCE`, which has precedence
+/// over `$CLICOLOR`.
+pub fn is_color_disabled() -> bool {
+    if is_no_color_set() {
+        return true;
+    }
+    var("CLICOLOR").as_deref() == Some("0") && !is_clicolor_force_set()
+}
+
+/// Tell whether the color control conventions ask for colors even when
+/// the output isn't a terminal, that is whether `$CLICOLOR_FORCE` is set
+/// (and isn't `0`) without `$NO_COLOR` asking for no color
+pub fn is_color_forced() -> bool {
+    is_clicolor_force_set() && !is_no_color_set()
+}
+
+fn is_no_color_set() -> bool {
+    var("NO_COLOR").is_some_and(|v| !v.is_empty())
+}
+
+fn is_clicolor_force_set() -> bool {
+    var("CLICOLOR_FORCE").is_some_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Tell whether we're running in Windows Terminal (as told by the
+/// `$WT_SESSION` env variable it sets), rather than in the legacy
+/// console host.
+///
+/// Windows Terminal answers the OSC color queries while the legacy
+/// console doesn't. But terminal-light doesn't query the terminal on
+/// Windows yet, so nothing in the crate uses this function: it's only
+/// informative for now. The check of the console host version (recent
+/// ones answer too), and the choice of the strategy based on both, are
+/// deferred until there's a Windows query path.
+pub fn is_windows_terminal() -> bool {
+    var("WT_SESSION").is_some_and(|session| !session.is_empty())
+}
+
+/// Terminals (as told by [term_program] or by the `$TERMINAL_EMULATOR`
+/// env variable) known to display, instead of ignoring, some of the OSC
+/// queries they don't answer
+pub const OSC_UNSAFE_TERMINALS: &[&str] = &["JetBrains-JediTerm"];
+
+/// Tell whether we're in a terminal known to display OSC queries (see
+/// [OSC_UNSAFE_TERMINALS])
+pub fn is_osc_unsafe() -> bool {
+    [term_program(), var("TERMINAL_EMULATOR")]
+        .iter()
+        .flatten()
+        .any(|name| OSC_UNSAFE_TERMINALS.contains(&name.as_str()))
+}
+
+/// Default background of the Linux virtual console
+pub const LINUX_CONSOLE_BG: AnsiColor = AnsiColor::new(0);
+
+/// Tell whether we're in a Linux virtual console, which doesn't
+/// answer the OSC query but has a well defined default background
+/// ([LINUX_CONSOLE_BG])
+pub fn is_linux_console() -> bool {
+    var("TERM").is_some_and(|term| term == "linux")
 }
 
 /// Parse the content of the COLORFGBG variable, which is supposed
 /// to be either like `17;45` or `0;default;15`, with the last token
 /// being the ansi code of the background color.
 ///
+/// The background may also be `default`, meaning the terminal's
+/// default background, which isn't a known ANSI color.
+///
 /// Note that there doesn't seem to be any authoritive documentation
 /// on the exact format.
-fn parse_colorfgbg(s: &str) -> Result<AnsiColor, TlError> {
-    let token: Vec<&str> = s.split(';').collect();
-    let bg = match token.len() {
-        2 => &token[1],
-        3 => &token[2],
-        _ => {
-            return Err(TlError::WrongFormat(s.to_string()));
-        }
+fn parse_colorfgbg(s: &str) -> Result<ColorFgBg, TlError> {
+    let (fg, middle, bg) = colorfgbg_tokens(s)?;
+    let color = |token: &str| match token {
+        "default" => Ok(None),
+        _ => token.parse().map(|code| Some(AnsiColor { code })),
     };
-    let code = bg.parse()?;
-    Ok(AnsiColor { code })
+    Ok(ColorFgBg {
+        foreground: color(fg)?,
+        background: color(bg)?,
+        had_default_field: middle == Some("default"),
+    })
+}
+
+/// Split the content of the COLORFGBG variable into its
+/// foreground, optional middle, and background tokens
+fn colorfgbg_tokens(s: &str) -> Result<(&str, Option<&str>, &str), TlError> {
+    let token: Vec<&str> = s.split(';').collect();
+    match token.len() {
+        2 => Ok((token[0], None, token[1])),
+        3 => Ok((token[0], Some(token[1]), token[2])),
+        _ => Err(TlError::WrongFormat(s.to_string())),
+    }
 }
 
 #[test]
 fn test_parse_color_fgbg() {
-    assert_eq!(parse_colorfgbg("17;45").unwrap(), AnsiColor::new(45));
-    assert_eq!(parse_colorfgbg("0;default;15").unwrap(), AnsiColor::new(15));
-    assert!(matches!(
-        parse_colorfgbg("15").unwrap_err(),
-        TlError::WrongFormat(_)
-    ));
+    let ansi = AnsiColor::new;
+    assert_eq!(
+        parse_colorfgbg("17;45").unwrap(),
+        ColorFgBg {
+            foreground: Some(ansi(17)),
+            background: Some(ansi(45)),
+            had_default_field: false,
+        },
+    );
+    assert_eq!(
+        parse_colorfgbg("0;default;15").unwrap(),
+        ColorFgBg {
+            foreground: Some(ansi(0)),
+            background: Some(ansi(15)),
+            had_default_field: true,
+        },
+    );
+    assert_eq!(
+        parse_colorfgbg("15;default").unwrap(),
+        ColorFgBg {
+            foreground: Some(ansi(15)),
+            background: None,
+            had_default_field: false,
+        },
+    );
+    assert_eq!(
+        parse_colorfgbg("7;default;default").unwrap(),
+        ColorFgBg {
+            foreground: Some(ansi(7)),
+            background: None,
+            had_default_field: true,
+        },
+    );
+    assert_eq!(
+        parse_colorfgbg("7;3;0").unwrap(),
+        ColorFgBg {
+            foreground: Some(ansi(7)),
+            background: Some(ansi(0)),
+            had_default_field: false,
+        },
+    );
+    assert_eq!(
+        parse_colorfgbg("default;0").unwrap(),
+        ColorFgBg {
+            foreground: None,
+            background: Some(ansi(0)),
+            had_default_field: false,
+        },
+    );
+    assert_eq!(
+        parse_colorfgbg("default;default;0").unwrap(),
+        ColorFgBg {
+            foreground: None,
+            background: Some(ansi(0)),
+            had_default_field: true,
+        },
+    );
+    for wrong in ["15", "", "1;2;3;4"] {
+        assert!(matches!(
+            parse_colorfgbg(wrong).unwrap_err(),
+            TlError::WrongFormat(_)
+        ));
+    }
     assert!(matches!(
         parse_colorfgbg("15;FF").unwrap_err(),
         TlError::ParseInt(_)
     ));
 }
+
+#[test]
+fn test_with_env() {
+    with_env(&[("COLORFGBG", Some("15;0"))], || {
+        assert_eq!(bg_color().unwrap(), AnsiColor::new(0));
+        assert_eq!(fg_color().unwrap(), AnsiColor::new(15));
+    });
+    with_env(&[("COLORFGBG", Some("default;0"))], || {
+        assert_eq!(bg_color().unwrap(), AnsiColor::new(0));
+        assert!(matches!(fg_color(), Err(TlError::DefaultForeground)));
+    });
+    with_env(&[("COLORFGBG", None)], || {
+        assert!(matches!(bg_color(), Err(TlError::NoColorFgBgEnv)));
+    });
+    with_env(
+        &[
+            ("INSIDE_EMACS", Some("29.1,vterm")),
+            ("TERM_PROGRAM", Some("WezTerm")),
+        ],
+        || assert_eq!(term_program().as_deref(), Some(EMACS)),
+    );
+    with_env(&[("INSIDE_EMACS", None), ("TERM_PROGRAM", None)], || {
+        assert_eq!(term_program(), None)
+    });
+    with_env(&[("TERM", Some("linux"))], || assert!(is_linux_console()));
+    with_env(
+        &[
+            ("NO_COLOR", None),
+            ("CLICOLOR", Some("0")),
+            ("CLICOLOR_FORCE", None),
+        ],
+        || assert!(is_color_disabled() && !is_color_forced()),
+    );
+    with_env(
+        &[
+            ("NO_COLOR", None),
+            ("CLICOLOR", Some("0")),
+            ("CLICOLOR_FORCE", Some("1")),
+        ],
+        || assert!(!is_color_disabled() && is_color_forced()),
+    );
+    with_env(
+        &[("NO_COLOR", Some("1")), ("CLICOLOR_FORCE", Some("1"))],
+        || assert!(is_color_disabled() && !is_color_forced()),
+    );
+    with_env(
+        &[
+            ("INSIDE_EMACS", None),
+            ("TERM_PROGRAM", None),
+            ("TERMINAL_EMULATOR", Some("JetBrains-JediTerm")),
+        ],
+        || assert!(is_osc_unsafe()),
+    );
+    with_env(
+        &[
+            ("TERM_PROGRAM", Some("WezTerm")),
+            ("TERMINAL_EMULATOR", None),
+        ],
+        || assert!(!is_osc_unsafe()),
+    );
+}
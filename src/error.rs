@@ -1,25 +1,84 @@
+use std::io;
+
 /// terminal-light error type
 #[derive(thiserror::Error, Debug)]
 pub enum TlError {
     #[error("IO error: {0}")]
     IO(#[from] std::io::Error),
 
-    #[cfg(unix)]
-    #[error("Xterm-query error: {0}")]
-    XtermQuery(#[from] xterm_query::XQError),
-
     #[error("Parse Int error: {0}")]
     ParseInt(#[from] std::num::ParseIntError),
 
+    #[error("Timeout waiting for the terminal")]
+    Timeout,
+
+    #[error("The terminal was disconnected during the query")]
+    Disconnected,
+
+    #[error("The terminal didn't answer the query")]
+    NotAnswered,
+
     #[error("Wrong answer format: {0}")]
     WrongFormat(String),
 
+    #[error("Unparsable answer of the terminal: {0:?}")]
+    UnparsableResponse(String),
+
+    #[error("Can't read or change the terminal attributes: {0}")]
+    TerminalAttributes(#[source] std::io::Error),
+
+    #[error("Terminal can't be queried")]
+    TerminalNotQueryable,
+
     #[error("No $COLORFGBG env variable")]
     NoColorFgBgEnv,
 
+    #[error("$COLORFGBG gives the default background, not a color")]
+    DefaultBackground,
+
+    #[error("$COLORFGBG gives the default foreground, not a color")]
+    DefaultForeground,
+
     #[error("Var error: {0}")]
     VarError(#[from] std::env::VarError),
 
     #[error("Unsupported platform")] // nothing works
     Unsupported,
 }
+
+/// Convert to an IO error, with a kind telling the cause when
+/// possible, the original error being the source
+impl From<TlError> for io::Error {
+    fn from(err: TlError) -> Self {
+        let kind = match &err {
+            TlError::IO(e) | TlError::TerminalAttributes(e) => e.kind(),
+            TlError::Timeout => io::ErrorKind::TimedOut,
+            TlError::Disconnected => io::ErrorKind::UnexpectedEof,
+            TlError::Unsupported | TlError::TerminalNotQueryable | TlError::NotAnswered => {
+                io::ErrorKind::Unsupported
+            }
+            TlError::ParseInt(_) | TlError::WrongFormat(_) | TlError::UnparsableResponse(_) => {
+                io::ErrorKind::InvalidData
+            }
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+#[test]
+fn test_into_io_error() {
+    use std::error::Error;
+    let err: io::Error = TlError::Timeout.into();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    let err: io::Error = TlError::Disconnected.into();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    let err: io::Error = TlError::UnparsableResponse("rgb:ff".to_string()).into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    // the underlying IO error of a tty operation is kept as source
+    let tty_err = TlError::TerminalAttributes(io::Error::from(io::ErrorKind::BrokenPipe));
+    assert!(tty_err.source().is_some());
+    let err: io::Error = tty_err.into();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    assert!(err.get_ref().is_some_and(|e| e.is::<TlError>()));
+}
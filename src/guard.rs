@@ -0,0 +1,80 @@
+use crate::*;
+
+/// A token keeping the terminal ready to be queried (in raw mode) until
+/// it's dropped, so that several queries are done without changing the
+/// terminal settings each time.
+///
+/// ```
+/// if let Ok(guard) = terminal_light::acquire_terminal() {
+///     let bg = guard.background_color();
+///     let fg = guard.foreground_color();
+/// } // the terminal is restored here
+/// ```
+#[derive(Debug)]
+pub struct TerminalGuard {
+    /// Whether we switched the terminal to raw mode (and must restore it)
+    #[cfg_attr(not(unix), allow(dead_code))]
+    switched_to_raw: bool,
+}
+
+/// Put the terminal in raw mode, if it's not already, for the life of
+/// the returned guard.
+///
+/// This fails when the terminal can't be queried (see [can_query_terminal]),
+/// which is always the case on non unix platforms.
+pub fn acquire_terminal() -> Result<TerminalGuard, TlError> {
+    #[cfg(unix)]
+    {
+        use crossterm::terminal::*;
+        // this also checks we're in the foreground, as a background
+        // process switching to raw mode would be stopped
+        if !tty::may_write_queries() || !can_query_terminal() {
+            return Err(TlError::TerminalNotQueryable);
+        }
+        let switched_to_raw = !is_raw_mode_enabled().map_err(TlError::TerminalAttributes)?;
+        if switched_to_raw {
+            enable_raw_mode().map_err(TlError::TerminalAttributes)?;
+        }
+        Ok(TerminalGuard { switched_to_raw })
+    }
+    #[cfg(not(unix))]
+    {
+        Err(TlError::Unsupported)
+    }
+}
+
+impl TerminalGuard {
+    /// Return a query using the terminal as prepared by the guard, which
+    /// must not be used after the guard is dropped
+    pub fn query(&self) -> Query {
+        Query::new().assume_raw(true)
+    }
+    /// Try to determine the background color of the terminal
+    /// (see [background_color])
+    pub fn background_color(&self) -> Result<Color, TlError> {
+        self.query().background_color()
+    }
+    /// Try to determine the foreground color of the terminal
+    /// (see [foreground_color])
+    pub fn foreground_color(&self) -> Result<Color, TlError> {
+        self.query().foreground_color()
+    }
+    /// Query the color of an entry of the terminal's palette
+    /// (see [palette_color])
+    pub fn palette_color(&self, index: u8) -> Result<Rgb, TlError> {
+        self.query()
+            .palette_colors(&[index])?
+            .first()
+            .map(|&(_, rgb)| rgb)
+            .ok_or(TlError::Unsupported)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if self.switched_to_raw {
+            let _ = crossterm::terminal::disable_raw_mode();
+        }
+    }
+}
@@ -31,6 +31,12 @@ match terminal_light::luma() {
 }
 ```
 
+The [mode] function gives this classification directly, and tells
+apart the "couldn't determine" case from the "medium" one.
+
+With more than two or three skins, a [SkinSet] can register them with
+their luma ranges and select the matching one.
+
 # Strategies
 
 ## `$COLORFGBG` strategy
@@ -68,29 +74,154 @@ Malus:
 
 * waiting for stdin with a timeout isn't implemented on Windows in this crate (help welcome)
 * this isn't instant, a delay of 10 ms to get the answer isn't unusual
-* if a terminal doesn't support the vt100 Status Report, we're waiting for 100ms
+* if a terminal doesn't support the vt100 Status Report, we're waiting for 100ms, twice (as
+  getting no answer at all may also be a sign of a lost query, so it's sent again)
 * it may fail on some terminal multiplexers
+* macOS's Terminal.app only answers from its version 2.8 (macOS 10.13 High Sierra): with older
+  versions, or when `$TERM_PROGRAM_VERSION` doesn't tell the version, the query isn't sent
 * if the timeout expires (because we are running over a slow ssh connection, for example),
   user-visible nonsense will be spewed to the terminal.
 
 ## Global strategy used by Terminal-light
 
-1. if we're on a unix-like platform, we try the escape sequence strategy
-2. if it failed or we're not on unix, we try the `$COLORFGBG` strategy
-3. without a solution, we return a `TlError::Unsupported` error
+1. if a parent process gave its detected background in the `$TERMINAL_LIGHT_CACHE` env variable (see `export_cache`), we return it
+2. if we're on a unix-like platform, in the foreground of an interactive terminal which isn't an Emacs one, not under a CI provider, and colors aren't disabled (see below), we try the escape sequence strategy
+3. if it failed or we're not on unix, we try the `$COLORFGBG` strategy
+4. if it failed and we're in a Linux virtual console (`$TERM` is `linux`), we return its default black background
+5. without a solution, we return a `TlError::Unsupported` error
+
+## Color control env variables
+
+The escape sequence strategy also follows the usual conventions about colors:
+
+* when `$NO_COLOR` is set (and not empty), the terminal isn't queried, whatever the other variables
+* else when `$CLICOLOR_FORCE` is set (and not `0`), the terminal is queried even when stdout isn't a terminal (eg when piping into a pager), the query being then written directly to the tty
+* else when `$CLICOLOR` is `0`, the terminal isn't queried
+
+The other strategies aren't affected: `$COLORFGBG` is still read.
 
 */
 
+mod cache;
+mod compare;
+mod contrast;
+mod detailed;
 pub mod env;
 mod error;
+mod guard;
+mod mode;
+mod name;
+mod query;
+mod reply;
+mod skin;
+mod snapshot;
+mod stats;
+mod terminal_io;
+mod watch;
 
+#[cfg(unix)]
+mod tty;
 #[cfg(unix)]
 mod xterm;
 
-use std::time::Duration;
-pub use {coolor::*, error::*};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+pub use {
+    cache::*, compare::*, contrast::*, coolor::*, detailed::*, error::*, guard::*, mode::*,
+    name::*, query::*, reply::*, skin::*, snapshot::*, terminal_io::TerminalIo, watch::*,
+};
+
+#[cfg(unix)]
+pub use {terminal_io::StreamIo, xterm::QueryState};
+
+/// The timeout of the queries when none is specified, in microseconds
+static DEFAULT_TIMEOUT_MICROS: AtomicU64 = AtomicU64::new(100_000);
+
+/// Change, for the whole process, the time to wait for the terminal's
+/// answer when no timeout is specified (default: 100ms).
+///
+/// This applies to the functions without a timeout argument, like
+/// [background_color] or [luma], and to the queries built with [Query::new]
+/// which don't set a [timeout](Query::timeout). An explicit timeout, given as
+/// argument or set on a query, still has precedence.
+///
+/// ```
+/// // all our users are over ssh
+/// terminal_light::set_default_timeout(std::time::Duration::from_millis(300));
+/// ```
+pub fn set_default_timeout(timeout: Duration) {
+    let micros = timeout.as_micros().try_into().unwrap_or(u64::MAX);
+    DEFAULT_TIMEOUT_MICROS.store(micros, Ordering::Relaxed);
+}
+
+/// Return the timeout of the queries when none is specified
+pub(crate) fn default_timeout() -> Duration {
+    Duration::from_micros(DEFAULT_TIMEOUT_MICROS.load(Ordering::Relaxed))
+}
+
+/// Tell whether both stdin and stdout are attached to an interactive
+/// terminal (a tty on unix, a console on Windows).
+///
+/// When it's not the case (eg when the output is piped to a file), the
+/// escape sequence strategy isn't tried, as the query would end in the
+/// output, but the `$COLORFGBG` one still is, so calling [luma] remains
+/// possible. Setting `$CLICOLOR_FORCE` makes the escape sequence strategy
+/// tried anyway, writing the query to the tty instead of stdout.
+pub fn is_interactive_terminal() -> bool {
+    use std::io::IsTerminal;
+    std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
 
-const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+/// Tell whether the terminal can be queried with escape sequences,
+/// that is whether we can open the tty and change its settings.
+///
+/// The check is done once and its result cached, so that repeated calls
+/// of [background_color] in a restricted environment (seccomp, some
+/// containers) go straight to the `$COLORFGBG` strategy.
+///
+/// This is `false`, without any probing, when the process isn't in the
+/// foreground of the terminal, and always `false` on non unix platforms.
+pub fn can_query_terminal() -> bool {
+    #[cfg(unix)]
+    {
+        tty::can_query_terminal()
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// Return, and forget, the bytes the user typed before or while the
+/// terminal was queried.
+///
+/// The answer of the terminal comes on the same input as the keys, so the
+/// keys typed before a query, or during it, are read by terminal-light
+/// (even when the query fails). They're kept, without
+/// the terminal's replies, for the application to handle them as if it had
+/// read them itself:
+///
+/// ```
+/// let luma = terminal_light::luma();
+/// let typed_ahead = terminal_light::take_pending_input();
+/// ```
+///
+/// This is always empty on non unix platforms.
+pub fn take_pending_input() -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        tty::take_pending_input()
+    }
+    #[cfg(not(unix))]
+    {
+        Vec::new()
+    }
+}
 
 /// Try to determine the background color of the terminal.
 ///
@@ -99,42 +230,133 @@ const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
 /// The result may come as Ansi or Rgb, depending on where
 /// the information has been found.
 ///
-/// If you want it as RGB:
+/// If you want it as RGB, use [background_rgb] or
 ///
 /// ```
+/// # use std::time::Duration;
+/// # const TIMEOUT: Duration = Duration::from_millis(100);
 /// let background_color_rgb = terminal_light::background_color_with_timeout(TIMEOUT)
 ///     .map(|c| c.rgb()); // may be an error
 /// ```
 pub fn background_color_with_timeout(timeout: Duration) -> Result<Color, TlError> {
-    #[cfg(unix)]
-    {
-        let xterm_color = xterm::query_bg_color(timeout);
-        if let Ok(xterm_color) = xterm_color {
-            return Ok(Color::Rgb(xterm_color));
-        }
-    }
-    let env_color = env::bg_color();
-    if let Ok(env_color) = env_color {
-        return Ok(Color::Ansi(env_color));
-    }
-    Err(TlError::Unsupported)
+    Query::new().timeout(timeout).background_color()
 }
 
 /// Try to determine the background color of the terminal.
 ///
-/// Times out after 100ms.
+/// Times out after 100ms (see [set_default_timeout]).
 ///
 /// The result may come as Ansi or Rgb, depending on where
 /// the information has been found.
 ///
-/// If you want it as RGB:
+/// If you want it as RGB, use [background_rgb].
+pub fn background_color() -> Result<Color, TlError> {
+    background_color_with_timeout(default_timeout())
+}
+
+/// Try to determine the background color of the terminal, using the
+/// given palette of the 16 standard ANSI colors when the color is found
+/// as ANSI (ie by the `$COLORFGBG` strategy).
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// This is useful when you know the real palette of the terminal (see
+/// [palette_colors]), so that the result and its luma reflect the colors
+/// really displayed.
+pub fn background_color_with_palette(palette: &[Rgb; 16]) -> Result<Color, TlError> {
+    Query::new().palette(palette).background_color()
+}
+
+/// Try to determine the background color of the terminal, as RGB.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// When the information comes as ANSI, it's converted using
+/// the standard xterm palette.
+pub fn background_rgb() -> Result<Rgb, TlError> {
+    background_color().map(|c| c.rgb())
+}
+
+/// Try to determine the background color of the terminal, both as RGB
+/// and as the nearest of the 16 standard ANSI colors.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// When the background is found as ANSI (by the `$COLORFGBG` strategy),
+/// its code is kept and the RGB is converted with the standard xterm
+/// palette.
+pub fn background_detailed() -> Result<DetailedColor, TlError> {
+    Query::new().background_detailed()
+}
+
+/// Query the background color of the terminal, with its channels at
+/// the precision given by the terminal (usually 16 bits) rather than
+/// truncated to 8 bits.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// Only the escape sequence strategy gives this color. Channels given
+/// by the terminal with less than 4 hex digits are zero-extended.
+pub fn background_color_16bit() -> Result<(u16, u16, u16), TlError> {
+    Query::new().background_color_16bit()
+}
+
+/// Try to determine the foreground (text) color of the terminal.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// The foreground is read with the same strategies than the
+/// background (see [background_color]).
+pub fn foreground_color() -> Result<Color, TlError> {
+    Query::new().foreground_color()
+}
+
+/// Try to determine the foreground (text) color of the terminal, as RGB.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// When the information comes as ANSI, it's converted using
+/// the standard xterm palette.
+pub fn foreground_rgb() -> Result<Rgb, TlError> {
+    foreground_color().map(|c| c.rgb())
+}
+
+/// Query the color of the terminal's cursor.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// Only the escape sequence strategy gives this color: it's queried
+/// independently of the background and foreground ones, so a terminal
+/// not answering this query doesn't prevent the other ones from working.
+pub fn cursor_color() -> Result<Color, TlError> {
+    Query::new().cursor_color()
+}
+
+/// Query the color of an entry of the terminal's palette (0 to 15 being
+/// the standard ANSI colors).
+///
+/// Times out after 100ms (see [set_default_timeout]).
+pub fn palette_color(index: u8) -> Result<Rgb, TlError> {
+    palette_colors(&[index])?
+        .first()
+        .map(|&(_, rgb)| rgb)
+        .ok_or(TlError::Unsupported)
+}
+
+/// Query the colors of several entries of the terminal's palette, with
+/// a single round trip.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// Entries the terminal didn't answer are absent from the returned list.
 ///
 /// ```
-/// let background_color_rgb = terminal_light::background_color()
-///     .map(|c| c.rgb()); // may be an error
+/// // get the 16 standard ANSI colors, as they're displayed by the terminal
+/// let indices: Vec<u8> = (0..16).collect();
+/// let palette = terminal_light::palette_colors(&indices);
 /// ```
-pub fn background_color() -> Result<Color, TlError> {
-    background_color_with_timeout(DEFAULT_TIMEOUT)
+pub fn palette_colors(indices: &[u8]) -> Result<Vec<(u8, Rgb)>, TlError> {
+    Query::new().palette_colors(indices)
 }
 
 /// Try to return the "luma" value of the terminal's background, characterizing
@@ -145,18 +367,205 @@ pub fn background_color() -> Result<Color, TlError> {
 /// You can say a terminal is "dark" when the luma is below 0.2 and
 /// "light" when it's over 0.9. If you need to choose a pivot between
 /// "rather dark" and "rather light" then 0.6 should do.
+///
+/// When the mode is forced with [set_mode_override], its extreme luma
+/// is returned without querying anything.
 pub fn luma_with_timeout(timeout: Duration) -> Result<f32, TlError> {
+    if let Some(luma) = overridden_luma() {
+        return Ok(luma);
+    }
     background_color_with_timeout(timeout).map(|c| c.luma())
 }
 
 /// Try to return the "luma" value of the terminal's background, characterizing
 /// the "light" of the color, going from 0 (black) to 1 (white).
 ///
-/// Times out after 100ms.
+/// Times out after 100ms (see [set_default_timeout]).
 ///
 /// You can say a terminal is "dark" when the luma is below 0.2 and
 /// "light" when it's over 0.9. If you need to choose a pivot between
 /// "rather dark" and "rather light" then 0.6 should do.
+///
+/// The luma is given at full precision: use [luma_rounded] to compare
+/// it stably to a threshold.
 pub fn luma() -> Result<f32, TlError> {
-    luma_with_timeout(DEFAULT_TIMEOUT)
+    luma_with_timeout(default_timeout())
+}
+
+/// Try to return the luma of the terminal's background (see [luma]),
+/// rounded to the given number of decimal places.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// [luma] returns the luma at full precision, so a value close to a
+/// threshold may be found on one side or the other of it depending on
+/// tiny differences. A rounded luma compares stably to thresholds
+/// having no more decimal places:
+///
+/// ```
+/// let should_use_light_skin = terminal_light::luma_rounded(2)
+///     .map_or(false, |luma| luma > 0.6);
+/// ```
+pub fn luma_rounded(decimals: u8) -> Result<f32, TlError> {
+    luma().map(|luma| round_luma(luma, decimals))
+}
+
+/// Round a luma to the given number of decimal places
+fn round_luma(luma: f32, decimals: u8) -> f32 {
+    let factor = 10f64.powi(decimals.into());
+    ((luma as f64 * factor).round() / factor) as f32
+}
+
+/// Try to return the luma of the terminal's background (see [luma]) on
+/// a 0 to 100 scale, rounded.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+pub fn luma_percent() -> Result<u8, TlError> {
+    luma().map(|luma| scale_luma(luma, 100))
+}
+
+/// Try to return the luma of the terminal's background (see [luma]) on
+/// a 0 to 255 scale, rounded.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+pub fn luma_u8() -> Result<u8, TlError> {
+    luma().map(|luma| scale_luma(luma, 255))
+}
+
+/// Scale a luma to the 0 to `max` range
+fn scale_luma(luma: f32, max: u8) -> u8 {
+    (luma.clamp(0.0, 1.0) * max as f32).round() as u8
+}
+
+/// Try to return the "luma" value of what's displayed behind the text,
+/// assuming the given backdrop is behind the terminal's window, which
+/// matters when the terminal's background is transparent.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// For an opaque background, this is the same as [luma].
+///
+/// ```
+/// // assume a dark desktop behind the terminal
+/// let luma = terminal_light::effective_luma(terminal_light::Rgb::new(30, 30, 30));
+/// ```
+pub fn effective_luma(backdrop: Rgb) -> Result<f32, TlError> {
+    Query::new().effective_luma(backdrop)
+}
+
+/// Try to return the luma of the terminal's background (see [luma]), as
+/// the median of several readings taken `interval` apart.
+///
+/// This is only useful for the rare terminals whose reported background
+/// fluctuates (transparent or animated backgrounds on some compositors):
+/// it takes at least `(samples - 1) * interval`, and each reading may wait
+/// for the timeout (see [set_default_timeout]), so prefer [luma] otherwise.
+///
+/// A `samples` of 0 is taken as 1, so there's always at least one reading.
+/// Failed readings are discarded. The error of the last one is returned
+/// when all of them fail.
+///
+/// ```
+/// let luma = terminal_light::luma_sampled(5, std::time::Duration::from_millis(20));
+/// ```
+pub fn luma_sampled(samples: usize, interval: Duration) -> Result<f32, TlError> {
+    let mut lumas = Vec::with_capacity(samples);
+    let mut last_error = None;
+    for i in 0..samples.max(1) {
+        if i > 0 {
+            std::thread::sleep(interval);
+        }
+        match luma() {
+            Ok(luma) => lumas.push(luma),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    median(&mut lumas).ok_or_else(|| last_error.unwrap_or(TlError::Unsupported))
+}
+
+/// Return the median of the values, or `None` if there's none
+fn median(values: &mut [f32]) -> Option<f32> {
+    values.sort_by(f32::total_cmp);
+    let middle = values.len() / 2;
+    match values.len() {
+        0 => None,
+        n if n % 2 == 0 => Some((values[middle - 1] + values[middle]) / 2.0),
+        _ => Some(values[middle]),
+    }
+}
+
+/// Start determining the luma of the terminal's background without blocking,
+/// and call the provided callback with the result when it's known.
+///
+/// This function returns immediately: the probe (see [luma]) runs on a new
+/// thread, so you can render a first frame with a neutral skin and re-render
+/// when the callback is called.
+///
+/// The callback is called exactly once, on the probing thread, not on the
+/// thread which called `luma_deferred`. It must thus be `Send`, and it should
+/// usually just hand the result to the main loop (with a channel, for example)
+/// rather than render by itself.
+///
+/// As the terminal's answer comes on stdin, the application shouldn't read
+/// stdin until the callback has been called.
+///
+/// When no thread can be started (eg on wasm), the probe is done and the
+/// callback called before returning, and `None` is returned.
+///
+/// ```
+/// let (tx, rx) = std::sync::mpsc::channel();
+/// terminal_light::luma_deferred(move |luma| {
+///     let _ = tx.send(luma);
+/// });
+/// // render a first frame, then
+/// let luma = rx.recv().unwrap();
+/// ```
+pub fn luma_deferred<F>(callback: F) -> Option<std::thread::JoinHandle<()>>
+where
+    F: FnOnce(Result<f32, TlError>) + Send + 'static,
+{
+    // the callback is shared so that it can still be called here
+    // when the thread can't be started
+    let callback = Arc::new(Mutex::new(Some(callback)));
+    let take =
+        |callback: &Mutex<Option<F>>| callback.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let thread_callback = Arc::clone(&callback);
+    let thread = std::thread::Builder::new().spawn(move || {
+        if let Some(callback) = take(&thread_callback) {
+            callback(luma());
+        }
+    });
+    if thread.is_err() {
+        if let Some(callback) = take(&callback) {
+            callback(luma());
+        }
+    }
+    thread.ok()
+}
+
+#[test]
+fn test_round_luma() {
+    assert_eq!(round_luma(0.5999999, 2), 0.6);
+    assert_eq!(round_luma(0.6000001, 2), 0.6);
+    assert_eq!(round_luma(0.123456, 3), 0.123);
+    assert_eq!(round_luma(0.55, 0), 1.0);
+}
+
+#[test]
+fn test_scale_luma() {
+    assert_eq!(scale_luma(0.0, 100), 0);
+    assert_eq!(scale_luma(0.456, 100), 46);
+    assert_eq!(scale_luma(1.0, 100), 100);
+    assert_eq!(scale_luma(1.2, 100), 100);
+    assert_eq!(scale_luma(-0.1, 255), 0);
+    assert_eq!(scale_luma(0.5, 255), 128);
+    assert_eq!(scale_luma(1.0, 255), 255);
+}
+
+#[test]
+fn test_median() {
+    assert_eq!(median(&mut []), None);
+    assert_eq!(median(&mut [0.3]), Some(0.3));
+    assert_eq!(median(&mut [0.9, 0.2, 0.25]), Some(0.25));
+    assert_eq!(median(&mut [0.2, 0.9, 0.3, 0.1]), Some(0.25));
 }
@@ -0,0 +1,236 @@
+use {
+    crate::*,
+    std::sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Maximal luma of a background considered as dark
+pub const DARK_MAX_LUMA: f32 = 0.2;
+
+/// Minimal luma of a background considered as light
+pub const LIGHT_MIN_LUMA: f32 = 0.85;
+
+#[cfg(all(feature = "pivot-05", feature = "pivot-06"))]
+compile_error!("only one of the `pivot-05` and `pivot-06` features can be enabled");
+
+#[cfg(not(any(feature = "pivot-05", feature = "pivot-06")))]
+compile_error!("one of the `pivot-05` and `pivot-06` features must be enabled");
+
+/// Luma separating the "rather dark" backgrounds from the "rather light"
+/// ones in [is_dark] and [is_light].
+///
+/// It's 0.6 with the default `pivot-06` feature, and 0.5 with the
+/// `pivot-05` one (with `default-features = false`).
+#[cfg(feature = "pivot-05")]
+pub const PIVOT_LUMA: f32 = 0.5;
+
+/// Luma separating the "rather dark" backgrounds from the "rather light"
+/// ones in [is_dark] and [is_light].
+///
+/// It's 0.6 with the default `pivot-06` feature, and 0.5 with the
+/// `pivot-05` one (with `default-features = false`).
+#[cfg(all(feature = "pivot-06", not(feature = "pivot-05")))]
+pub const PIVOT_LUMA: f32 = 0.6;
+
+/// Whether the terminal is dark or light
+#[derive(Debug)]
+pub enum Mode {
+    /// The background's luma is below [DARK_MAX_LUMA]
+    Dark,
+    /// The background's luma is over [LIGHT_MIN_LUMA]
+    Light,
+    Unknown {
+        reason: UnknownReason,
+    },
+}
+
+/// Why the mode of the terminal isn't known
+#[derive(Debug)]
+pub enum UnknownReason {
+    /// The background couldn't be determined
+    Error(TlError),
+    /// The background was determined but it's neither dark nor
+    /// light; its luma is given
+    Medium(f32),
+}
+
+impl Mode {
+    /// Classify a background from its luma
+    pub fn from_luma(luma: f32) -> Self {
+        if luma < DARK_MAX_LUMA {
+            Self::Dark
+        } else if luma > LIGHT_MIN_LUMA {
+            Self::Light
+        } else {
+            Self::Unknown {
+                reason: UnknownReason::Medium(luma),
+            }
+        }
+    }
+    /// Tell whether both modes are dark, both light, or both unknown
+    #[cfg(unix)]
+    pub(crate) fn is_same(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl From<Result<f32, TlError>> for Mode {
+    fn from(luma: Result<f32, TlError>) -> Self {
+        match luma {
+            Ok(luma) => Self::from_luma(luma),
+            Err(e) => Self::Unknown {
+                reason: UnknownReason::Error(e),
+            },
+        }
+    }
+}
+
+/// The mode forced with [set_mode_override]: 0 for none, 1 for dark,
+/// 2 for light
+static MODE_OVERRIDE: AtomicU8 = AtomicU8::new(0);
+
+/// Force, for the whole process, the mode of the terminal, usually
+/// from the `--dark`/`--light`/`--auto` arguments of a CLI tool.
+///
+/// With `Some(Mode::Dark)` or `Some(Mode::Light)`, [luma] and [mode]
+/// return the extreme luma (0 or 1) and the given mode without any I/O,
+/// whatever the terminal or its `$COLORFGBG` env variable say: the
+/// override has precedence over all the strategies. `None` (or
+/// `Some(Mode::Unknown {..})`) restores the real detection.
+///
+/// The functions working on the color, like [background_color], and
+/// the queries built with [Query], aren't affected. Neither is the
+/// luma already cached by [cached_luma], so set the override first.
+///
+/// ```
+/// # let arg = "--light";
+/// terminal_light::set_mode_override(match arg {
+///     "--dark" => Some(terminal_light::Mode::Dark),
+///     "--light" => Some(terminal_light::Mode::Light),
+///     _ => None, // --auto
+/// });
+/// assert_eq!(terminal_light::luma().unwrap(), 1.0);
+/// ```
+pub fn set_mode_override(mode: Option<Mode>) {
+    MODE_OVERRIDE.store(override_code(mode), Ordering::Relaxed);
+}
+
+/// Return the luma of the mode forced with [set_mode_override], if any
+pub(crate) fn overridden_luma() -> Option<f32> {
+    override_luma(MODE_OVERRIDE.load(Ordering::Relaxed))
+}
+
+/// Return the code of the given override in [MODE_OVERRIDE]
+fn override_code(mode: Option<Mode>) -> u8 {
+    match mode {
+        Some(Mode::Dark) => 1,
+        Some(Mode::Light) => 2,
+        _ => 0,
+    }
+}
+
+/// Return the luma of the override with the given code
+fn override_luma(code: u8) -> Option<f32> {
+    match code {
+        1 => Some(0.0),
+        2 => Some(1.0),
+        _ => None,
+    }
+}
+
+/// Try to determine whether the terminal is dark or light.
+///
+/// Times out after 100ms (see [set_default_timeout]), and doesn't query
+/// anything when the mode is forced with [set_mode_override].
+///
+/// When the mode is unknown, the reason tells whether the background
+/// couldn't be determined (you may then want to use your default skin)
+/// or whether it's a medium one (where a neutral skin may be better).
+///
+/// ```
+/// use terminal_light::{Mode, UnknownReason};
+///
+/// match terminal_light::mode() {
+///     Mode::Dark => { /* use a dark skin */ }
+///     Mode::Light => { /* use a light skin */ }
+///     Mode::Unknown { reason: UnknownReason::Medium(_) } => { /* use a neutral skin */ }
+///     Mode::Unknown { reason: UnknownReason::Error(_) } => { /* use the default skin */ }
+/// }
+/// ```
+pub fn mode() -> Mode {
+    luma().into()
+}
+
+#[test]
+fn test_mode_from_luma() {
+    assert!(matches!(Mode::from_luma(0.05), Mode::Dark));
+    assert!(matches!(Mode::from_luma(0.95), Mode::Light));
+    assert!(matches!(
+        Mode::from_luma(0.5),
+        Mode::Unknown {
+            reason: UnknownReason::Medium(_)
+        }
+    ));
+    assert!(matches!(
+        Mode::from(Err(TlError::Unsupported)),
+        Mode::Unknown {
+            reason: UnknownReason::Error(TlError::Unsupported)
+        }
+    ));
+}
+
+/// Try to determine whether the terminal's background is rather dark,
+/// that is whether its luma is below [PIVOT_LUMA].
+///
+/// Times out after 100ms (see [set_default_timeout]). The mode forced
+/// with [set_mode_override] has precedence.
+///
+/// Unlike [mode], there's no medium background: each one is either
+/// rather dark or rather light.
+pub fn is_dark() -> Result<bool, TlError> {
+    luma().map(|luma| luma < PIVOT_LUMA)
+}
+
+/// Try to determine whether the terminal's background is rather light,
+/// that is whether its luma is at least [PIVOT_LUMA] (see [is_dark])
+pub fn is_light() -> Result<bool, TlError> {
+    is_dark().map(|dark| !dark)
+}
+
+/// Tell whether the escape sequence strategy and the `$COLORFGBG` one
+/// agree on the terminal being dark or light.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// `$COLORFGBG` is set when the terminal starts, and isn't updated when
+/// its theme changes, so a disagreement usually means it's stale, and
+/// that it shouldn't be trusted when the terminal can't be queried.
+///
+/// An error is returned when one of the strategies doesn't give a
+/// background, as there's nothing to compare then.
+pub fn strategies_agree() -> Result<bool, TlError> {
+    Query::new().strategies_agree()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_is_same() {
+    assert!(Mode::from_luma(0.05).is_same(&Mode::Dark));
+    assert!(!Mode::from_luma(0.05).is_same(&Mode::from_luma(0.95)));
+    assert!(Mode::from_luma(0.5).is_same(&Mode::from_luma(0.6)));
+}
+
+#[test]
+fn test_mode_override() {
+    // the global override isn't set here, as the tests
+    // calling luma or mode run concurrently
+    let luma = |mode| override_luma(override_code(mode));
+    assert_eq!(luma(Some(Mode::Dark)), Some(0.0));
+    assert_eq!(luma(Some(Mode::Light)), Some(1.0));
+    assert_eq!(luma(None), None);
+    assert_eq!(
+        luma(Some(Mode::Unknown {
+            reason: UnknownReason::Medium(0.5)
+        })),
+        None
+    );
+}
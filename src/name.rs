@@ -0,0 +1,58 @@
+use crate::*;
+
+/// The names a background can be described with, and their
+/// reference colors
+const NAMES: &[(&str, Rgb)] = &[
+    ("black", Rgb::new(0, 0, 0)),
+    ("dark gray", Rgb::new(64, 64, 64)),
+    ("gray", Rgb::new(128, 128, 128)),
+    ("light gray", Rgb::new(192, 192, 192)),
+    ("white", Rgb::new(255, 255, 255)),
+    ("dark red", Rgb::new(96, 0, 0)),
+    ("red", Rgb::new(200, 0, 0)),
+    ("dark green", Rgb::new(0, 80, 0)),
+    ("green", Rgb::new(0, 180, 0)),
+    ("dark blue", Rgb::new(0, 0, 96)),
+    ("blue", Rgb::new(0, 0, 220)),
+    ("dark cyan", Rgb::new(0, 64, 72)),
+    ("cyan", Rgb::new(0, 200, 200)),
+    ("dark magenta", Rgb::new(80, 0, 80)),
+    ("magenta", Rgb::new(200, 0, 200)),
+    ("brown", Rgb::new(100, 60, 20)),
+    ("yellow", Rgb::new(230, 220, 0)),
+    ("beige", Rgb::new(240, 230, 200)),
+];
+
+/// Return the name of the nearest of the reference colors
+fn color_name(rgb: Rgb) -> &'static str {
+    NAMES
+        .iter()
+        .min_by(|(_, a), (_, b)| rgb.distance_to(*a).total_cmp(&rgb.distance_to(*b)))
+        .map_or("black", |(name, _)| name)
+}
+
+/// Return a coarse name of the terminal's background, like "black",
+/// "dark gray", "dark blue" or "beige", for logs and diagnostics.
+///
+/// The name is the one of the nearest color in a small fixed set
+/// (the grays from black to white, the primary and secondary colors
+/// in dark and bright variants, brown and beige).
+///
+/// ```
+/// if let Ok(name) = terminal_light::background_color_name() {
+///     println!("detected: {name} background");
+/// }
+/// ```
+pub fn background_color_name() -> Result<&'static str, TlError> {
+    background_rgb().map(color_name)
+}
+
+#[test]
+fn test_color_name() {
+    assert_eq!(color_name(Rgb::new(0, 0, 0)), "black");
+    assert_eq!(color_name(Rgb::new(40, 44, 52)), "dark gray");
+    assert_eq!(color_name(Rgb::new(250, 250, 250)), "white");
+    assert_eq!(color_name(Rgb::new(238, 232, 213)), "beige"); // solarized base2
+    assert_eq!(color_name(Rgb::new(0, 43, 54)), "dark cyan"); // solarized dark
+    assert_eq!(color_name(Rgb::new(0, 0, 120)), "dark blue");
+}
@@ -0,0 +1,396 @@
+use {
+    crate::{terminal_io::SharedIo, *},
+    std::time::Duration,
+};
+
+/// A configurable query of the terminal's background.
+///
+/// The free functions of the crate, like [background_color] or [luma],
+/// use a default query. Build one when you need to tune the handshake
+/// with the terminal:
+///
+/// ```
+/// use std::time::Duration;
+///
+/// let luma = terminal_light::Query::new()
+///     .timeout(Duration::from_millis(300))
+///     .da1_before_osc(true)
+///     .luma();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub(crate) timeout: Duration,
+    pub(crate) barrier_timeout: Option<Duration>,
+    pub(crate) da1_before_osc: bool,
+    pub(crate) linux_console_fallback: bool,
+    pub(crate) palette: Option<[Rgb; 16]>,
+    pub(crate) retries: u8,
+    pub(crate) quiet: bool,
+    pub(crate) assume_raw: bool,
+    pub(crate) skip_in_ci: bool,
+    /// Whether the background exported by [export_cache] may be used;
+    /// it's not when watching, as the background may have changed since
+    pub(crate) use_exported: bool,
+    pub(crate) io: Option<SharedIo>,
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Self {
+            timeout: default_timeout(),
+            barrier_timeout: None,
+            da1_before_osc: false,
+            linux_console_fallback: true,
+            palette: None,
+            retries: 1,
+            quiet: false,
+            assume_raw: false,
+            skip_in_ci: true,
+            use_exported: true,
+            io: None,
+        }
+    }
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set the time to wait for the terminal's answer (default: 100ms,
+    /// unless changed with [set_default_timeout])
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Set the time to wait for the terminal to start answering (default:
+    /// the same as the [timeout](Self::timeout)).
+    ///
+    /// The query is followed by a Status Report query, which almost all
+    /// terminals answer, and fast, even when they don't support the color
+    /// query. A short barrier timeout lets us conclude quickly when nothing
+    /// comes, while a longer overall timeout still lets a slow color reply
+    /// be read once the terminal has started sending it (eg over ssh).
+    pub fn barrier_timeout(mut self, barrier_timeout: Duration) -> Self {
+        self.barrier_timeout = Some(barrier_timeout);
+        self
+    }
+    /// Set how many times the query is sent again when the terminal
+    /// answered nothing before the timeout (default: 1).
+    ///
+    /// When the answer to the Status Report comes without the color, the
+    /// terminal doesn't support the color query, and it's not sent again.
+    /// But when nothing at all comes, the queries or the answers may have
+    /// been lost (on a lossy connection, for example).
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+    /// Send a DA1 (Primary Device Attributes) query, and wait for its answer,
+    /// before the OSC query (default: false).
+    ///
+    /// This is for terminals which would ignore the OSC color query until
+    /// a DA1 query has been answered in the session. No such terminal is
+    /// known yet: the option is there to try it when a terminal doesn't
+    /// answer while it should (please report it then). It costs an
+    /// additional round trip.
+    pub fn da1_before_osc(mut self, da1_before_osc: bool) -> Self {
+        self.da1_before_osc = da1_before_osc;
+        self
+    }
+    /// When no other strategy worked and we're in a Linux virtual console
+    /// (`$TERM` is `linux`), return the console's default background, which
+    /// is black (default: true).
+    ///
+    /// Set it to false if you'd rather get an error in this case.
+    pub fn linux_console_fallback(mut self, linux_console_fallback: bool) -> Self {
+        self.linux_console_fallback = linux_console_fallback;
+        self
+    }
+    /// Don't query the terminal with escape sequences when running under
+    /// a CI provider (see [env::is_ci]), where there's usually no real
+    /// terminal (default: true).
+    ///
+    /// Set it to false if you really run an interactive terminal in
+    /// a CI-like environment.
+    pub fn skip_in_ci(mut self, skip_in_ci: bool) -> Self {
+        self.skip_in_ci = skip_in_ci;
+        self
+    }
+    /// Send the escape sequences and read the answers with the given
+    /// transport instead of the tty (default: none, the tty is used).
+    ///
+    /// The env variables describe the real terminal, not the one behind the
+    /// transport, so they aren't used at all: the query isn't skipped in an
+    /// Emacs terminal or under CI, and there's no fallback on the exported
+    /// background, `$COLORFGBG` or the Linux console when it fails.
+    ///
+    /// This is only used on unix, as there's no escape sequence strategy
+    /// on other platforms.
+    pub fn terminal_io(mut self, io: Box<dyn TerminalIo>) -> Self {
+        self.io = Some(SharedIo::new(io));
+        self
+    }
+    /// Consider the terminal is already in raw mode, and don't change
+    /// its settings (default: false).
+    ///
+    /// By default, the terminal is switched to raw mode for the query,
+    /// then restored. Applications managing the terminal settings by
+    /// themselves may prefer to keep them untouched, but then the terminal
+    /// must be, during the query, in non canonical mode (so that the answer
+    /// can be read before a newline) and without echo (so that the answer
+    /// isn't displayed). When the terminal is found in canonical mode,
+    /// the query isn't sent.
+    pub fn assume_raw(mut self, assume_raw: bool) -> Self {
+        self.assume_raw = assume_raw;
+        self
+    }
+    /// Never send OSC queries to the terminals known to display them
+    /// instead of ignoring them (see [env::OSC_UNSAFE_TERMINALS]), so that
+    /// only the env based strategies are used there (default: false).
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+    /// Set the actual palette of the terminal's 16 standard ANSI colors,
+    /// used to convert the ANSI colors found by the `$COLORFGBG` strategy
+    /// (default: none, the colors are returned as ANSI and converted with
+    /// the standard xterm palette).
+    ///
+    /// You may get this palette from [palette_colors](Self::palette_colors).
+    pub fn palette(mut self, palette: &[Rgb; 16]) -> Self {
+        self.palette = Some(*palette);
+        self
+    }
+    /// Return the color to give for an ANSI color found without querying
+    /// the terminal, taking the palette into account
+    fn ansi_color(&self, ansi: AnsiColor) -> Color {
+        match self.palette {
+            Some(palette) if ansi.code < 16 => Color::Rgb(palette[ansi.code as usize]),
+            _ => Color::Ansi(ansi),
+        }
+    }
+    /// Try to determine the background color of the terminal.
+    ///
+    /// The result may come as Ansi or Rgb, depending on where
+    /// the information has been found.
+    ///
+    /// With the `metrics` feature, the attempts and their outcomes
+    /// are counted.
+    pub fn background_color(&self) -> Result<Color, TlError> {
+        self.background_color_alpha().map(|(color, _)| color)
+    }
+    /// Try to determine the background color of the terminal, with its
+    /// alpha (255 for an opaque background, which is the case unless the
+    /// terminal tells otherwise)
+    fn background_color_alpha(&self) -> Result<(Color, u8), TlError> {
+        if self.use_exported && self.io.is_none() {
+            if let Some(rgb) = exported_background() {
+                return Ok((Color::Rgb(rgb), 255));
+            }
+        }
+        stats::record(stats::Event::Attempt);
+        #[cfg(unix)]
+        {
+            match xterm::query_bg_rgba(self) {
+                Ok((xterm_color, alpha)) => {
+                    stats::record(stats::Event::OscSuccess);
+                    return Ok((Color::Rgb(xterm_color), alpha));
+                }
+                Err(e) => {
+                    stats::record_failure(&e);
+                    if self.io.is_some() {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        match env::bg_color() {
+            Ok(env_color) => {
+                stats::record(stats::Event::EnvSuccess);
+                return Ok((self.ansi_color(env_color), 255));
+            }
+            Err(e) => stats::record_failure(&e),
+        }
+        if self.linux_console_fallback && env::is_linux_console() {
+            return Ok((self.ansi_color(env::LINUX_CONSOLE_BG), 255));
+        }
+        Err(TlError::Unsupported)
+    }
+    /// Try to determine the background color of the terminal, both as RGB
+    /// and as the nearest of the 16 standard ANSI colors, using the
+    /// [palette](Self::palette) when given.
+    pub fn background_detailed(&self) -> Result<DetailedColor, TlError> {
+        self.background_color()
+            .map(|color| DetailedColor::new(color, self.palette.as_ref()))
+    }
+    /// Query the background color of the terminal, with the channels at
+    /// the precision the terminal gives them (usually 16 bits, channels
+    /// given with less digits are zero-extended, not scaled).
+    ///
+    /// There's no fallback here: only the escape sequence strategy gives
+    /// this precision.
+    pub fn background_color_16bit(&self) -> Result<(u16, u16, u16), TlError> {
+        #[cfg(unix)]
+        {
+            xterm::query_bg_color_16bit(self)
+        }
+        #[cfg(not(unix))]
+        {
+            Err(TlError::Unsupported)
+        }
+    }
+    /// Try to determine the foreground (text) color of the terminal.
+    ///
+    /// The result may come as Ansi or Rgb, depending on where
+    /// the information has been found.
+    pub fn foreground_color(&self) -> Result<Color, TlError> {
+        #[cfg(unix)]
+        {
+            let xterm_color = xterm::query_fg_color(self);
+            if xterm_color.is_ok() || self.io.is_some() {
+                return xterm_color.map(Color::Rgb);
+            }
+        }
+        let env_color = env::fg_color();
+        if let Ok(env_color) = env_color {
+            return Ok(self.ansi_color(env_color));
+        }
+        Err(TlError::Unsupported)
+    }
+    /// Query the color of the terminal's cursor.
+    ///
+    /// There's no fallback here: the cursor color is only known when
+    /// the terminal answers the OSC 12 query.
+    pub fn cursor_color(&self) -> Result<Color, TlError> {
+        #[cfg(unix)]
+        {
+            xterm::query_cursor_color(self).map(Color::Rgb)
+        }
+        #[cfg(not(unix))]
+        {
+            Err(TlError::Unsupported)
+        }
+    }
+    /// Query the colors of the given entries of the terminal's palette
+    /// (0 to 15 being the standard ANSI colors), with a single round trip.
+    ///
+    /// There's no fallback here: the palette is only known when the terminal
+    /// answers the OSC 4 queries. Entries the terminal didn't answer are
+    /// absent from the returned list.
+    pub fn palette_colors(&self, indices: &[u8]) -> Result<Vec<(u8, Rgb)>, TlError> {
+        #[cfg(unix)]
+        {
+            xterm::query_palette_colors(indices, self)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = indices;
+            Err(TlError::Unsupported)
+        }
+    }
+    /// Query the foreground, background and cursor colors of the terminal,
+    /// and its 16 colors palette, with a single round trip.
+    ///
+    /// There's no fallback here: the snapshot only has the colors the
+    /// terminal answered, and it's an error when it answered none.
+    pub fn full_snapshot(&self) -> Result<FullSnapshot, TlError> {
+        #[cfg(unix)]
+        {
+            xterm::query_snapshot(self)
+        }
+        #[cfg(not(unix))]
+        {
+            Err(TlError::Unsupported)
+        }
+    }
+    /// Try to return the "luma" value of the terminal's background, from
+    /// 0 (black) to 1 (white).
+    pub fn luma(&self) -> Result<f32, TlError> {
+        self.background_color().map(|c| c.luma())
+    }
+    /// Try to return the "luma" value of what's really displayed behind
+    /// the text, from 0 (black) to 1 (white), assuming the given backdrop
+    /// is behind the terminal's window.
+    ///
+    /// When the terminal reports a transparent background, its color is
+    /// composited over the backdrop before the luma is computed. For an
+    /// opaque background, this is the same as [luma](Self::luma).
+    pub fn effective_luma(&self, backdrop: Rgb) -> Result<f32, TlError> {
+        self.background_color_alpha()
+            .map(|(color, alpha)| composite(color.rgb(), alpha, backdrop).luma())
+    }
+    /// Try to determine whether the terminal is dark or light
+    pub fn mode(&self) -> Mode {
+        self.luma().into()
+    }
+    /// Tell whether the escape sequence strategy and the `$COLORFGBG`
+    /// one give the same [Mode], both being tried.
+    ///
+    /// An error is returned when one of them doesn't give a background,
+    /// or when a [transport](Self::terminal_io) is set.
+    pub fn strategies_agree(&self) -> Result<bool, TlError> {
+        #[cfg(unix)]
+        {
+            if self.io.is_some() {
+                return Err(TlError::Unsupported);
+            }
+            let osc_luma = xterm::query_bg_rgba(self)?.0.luma();
+            let env_luma = self.ansi_color(env::bg_color()?).luma();
+            Ok(Mode::from_luma(osc_luma).is_same(&Mode::from_luma(env_luma)))
+        }
+        #[cfg(not(unix))]
+        {
+            Err(TlError::Unsupported)
+        }
+    }
+}
+
+/// Composite a color with the given alpha over an opaque backdrop
+fn composite(rgb: Rgb, alpha: u8, backdrop: Rgb) -> Rgb {
+    let mix = |c: u8, b: u8| {
+        let a = alpha as f32 / 255.0;
+        (c as f32 * a + b as f32 * (1.0 - a)).round() as u8
+    };
+    Rgb::new(
+        mix(rgb.r, backdrop.r),
+        mix(rgb.g, backdrop.g),
+        mix(rgb.b, backdrop.b),
+    )
+}
+
+#[test]
+fn test_composite() {
+    let black = Rgb::new(0, 0, 0);
+    let white = Rgb::new(255, 255, 255);
+    assert_eq!(composite(black, 255, white), black);
+    assert_eq!(composite(black, 0, white), white);
+    assert_eq!(composite(black, 128, white), Rgb::new(127, 127, 127));
+    assert_eq!(
+        composite(Rgb::new(200, 100, 0), 51, Rgb::new(0, 0, 100)),
+        Rgb::new(40, 20, 80),
+    );
+}
+
+#[test]
+fn test_ansi_color_with_palette() {
+    let mut palette = [Rgb::new(0, 0, 0); 16];
+    palette[4] = Rgb::new(0x1e, 0x1e, 0x2e);
+    let query = Query::new().palette(&palette);
+    assert!(matches!(
+        query.ansi_color(AnsiColor::new(4)),
+        Color::Rgb(Rgb {
+            r: 0x1e,
+            g: 0x1e,
+            b: 0x2e
+        }),
+    ));
+    // outside of the 16 standard colors, the palette doesn't apply
+    assert!(matches!(
+        query.ansi_color(AnsiColor::new(236)),
+        Color::Ansi(AnsiColor { code: 236 }),
+    ));
+    assert!(matches!(
+        Query::new().ansi_color(AnsiColor::new(4)),
+        Color::Ansi(AnsiColor { code: 4 }),
+    ));
+}
@@ -0,0 +1,446 @@
+use crate::*;
+
+/// Parse the answer of the terminal to the background color query (the
+/// "dynamic colors" OSC 11 query), as captured from the tty.
+///
+/// This is mostly useful to check how terminal-light handles the answer
+/// of a specific terminal, for example when reporting a bug.
+///
+/// ```
+/// use terminal_light::{parse_reply, Rgb};
+///
+/// let color = parse_reply(b"\x1b]11;rgb:2828/2c2c/3434\x1b\\").unwrap();
+/// assert_eq!(color.rgb(), Rgb::new(0x28, 0x2c, 0x34));
+/// ```
+pub fn parse_reply(bytes: &[u8]) -> Result<Color, TlError> {
+    parse_color_reply(11, &decode(bytes)).map(|(rgb, _)| Color::Rgb(rgb))
+}
+
+/// Return the background color given by the answer of the terminal
+/// to the OSC 11 query, when this answer was captured by other means
+/// (a recording, a remote agent, etc.).
+///
+/// Only the answer is parsed: the terminal isn't queried, and the env
+/// based strategies aren't tried. This is the same as [parse_reply].
+///
+/// ```
+/// let bg = terminal_light::background_color_from_reply(b"\x1b]11;rgb:0000/0000/0000\x07");
+/// assert!(bg.is_ok_and(|bg| bg.luma() == 0.0));
+/// ```
+pub fn background_color_from_reply(bytes: &[u8]) -> Result<Color, TlError> {
+    parse_reply(bytes)
+}
+
+/// Decode the bytes received from the terminal, replacing the 8-bit C1
+/// controls some terminals send (raw or UTF-8 encoded) with their 7-bit
+/// equivalent
+pub(crate) fn decode(bytes: &[u8]) -> String {
+    let mut decoded = Vec::with_capacity(bytes.len());
+    for &b in bytes {
+        let seven_bits: &[u8] = match b {
+            0x9b => b"\x1b[",
+            0x9c => b"\x1b\\",
+            0x9d => b"\x1b]",
+            _ => {
+                decoded.push(b);
+                continue;
+            }
+        };
+        match decoded.last() {
+            Some(0xc2) => {
+                // the C1 control is UTF-8 encoded
+                decoded.pop();
+            }
+            Some(&prev) if prev >= 0x80 => {
+                // part of another UTF-8 encoded char
+                decoded.push(b);
+                continue;
+            }
+            _ => {}
+        }
+        decoded.extend_from_slice(seven_bits);
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Return what remains of the (decoded) answer of the terminal once
+/// the replies to our queries are removed, that is the keys the user
+/// typed during the query.
+///
+/// The removed replies are the OSC ones (`ESC]...` up to a BEL or a ST),
+/// the private CSI ones (`ESC[?...` up to their final byte, like the
+/// DA1 answer), and the answers to the Status Report (`ESC[0n` and
+/// `ESC[3n`). Other escape sequences, like the ones of the arrow keys,
+/// are kept.
+#[cfg(unix)]
+pub(crate) fn strip_replies(answer: &str) -> String {
+    let mut input = String::new();
+    let mut rest = answer;
+    while let Some(start) = rest.find('\x1b') {
+        input.push_str(&rest[..start]);
+        let sequence = &rest[start..];
+        let len = if let Some(osc) = sequence.strip_prefix("\x1b]") {
+            // a truncated reply ends at the next sequence
+            osc.find(['\x07', '\x1b']).map_or(sequence.len(), |end| {
+                let terminator = &osc[end..];
+                let terminator_len = if terminator.starts_with('\x07') {
+                    1
+                } else if terminator.starts_with("\x1b\\") {
+                    2
+                } else {
+                    0
+                };
+                2 + end + terminator_len
+            })
+        } else if let Some(csi) = sequence.strip_prefix("\x1b[?") {
+            csi.find(|c| ('\x40'..='\x7e').contains(&c))
+                .map_or(sequence.len(), |end| end + 4)
+        } else if sequence.starts_with("\x1b[0n") || sequence.starts_with("\x1b[3n") {
+            4
+        } else {
+            input.push('\x1b');
+            1
+        };
+        rest = &sequence[len..];
+    }
+    input.push_str(rest);
+    input
+}
+
+/// Parse the answer to a "dynamic color" query, returning the color
+/// and its alpha
+pub(crate) fn parse_color_reply(code: u8, answer: &str) -> Result<(Rgb, u8), TlError> {
+    // The string we receive is like `"]11;rgb:<red>/<green>/<blue>\\"`
+    // where `<red>`, `<green>`, and `<blue>` are usually 4 hex digits.
+    // Most terminals don't support such precision so they fill the 4 digits
+    // by repeating their 2 digits precision.
+    // For example, supposing the background is in #38A4C9 (blue),
+    // then we receive `"\u{1b}]11;rgb:3838/a4a4/c9c9\u{1b}\\"`.
+    // We read only the most significant hex digits which are good enough
+    // in all cases.
+    parse_rgba(find_color_reply(code, answer)?)
+}
+
+/// Parse the answer to a "dynamic color" query, returning the channels
+/// as they were given by the terminal, without scaling them to 8 bits
+#[cfg(unix)]
+pub(crate) fn parse_color_reply_16bit(code: u8, answer: &str) -> Result<(u16, u16, u16), TlError> {
+    let raw_color = find_color_reply(code, answer)?;
+    let channels = raw_channels(raw_color)
+        .ok_or_else(|| TlError::UnparsableResponse(raw_color.to_string()))?;
+    let channel = |i: usize| u16::from_str_radix(channels[i], 16);
+    Ok((channel(0)?, channel(1)?, channel(2)?))
+}
+
+/// Return the color part of the reply to a "dynamic color" query
+fn find_color_reply(code: u8, answer: &str) -> Result<&str, TlError> {
+    let prefix = format!("]{code};");
+    // Some terminals echo the query before answering it, and some add
+    // fields (like a repetition of the code) before the color
+    osc_replies(answer)
+        .filter_map(|reply| reply.strip_prefix(&prefix))
+        .filter(|&fields| fields != "?")
+        .map(|fields| {
+            fields
+                .split(';')
+                .find(|field| field.starts_with("rgb") || field.starts_with('#'))
+                .unwrap_or(fields)
+        })
+        .next()
+        .ok_or(TlError::NotAnswered)
+}
+
+/// Split the answer of the terminal into its OSC replies (without
+/// their ESC introducer and BEL or ST terminator)
+fn osc_replies(answer: &str) -> impl Iterator<Item = &str> {
+    answer.split(['\x1b', '\x07'])
+}
+
+/// Parse the answers to palette queries, which are each like
+/// `ESC]4;<index>;rgb:<red>/<green>/<blue>BEL` (or ended with ST)
+#[cfg(unix)]
+pub(crate) fn parse_palette_replies(s: &str) -> Vec<(u8, Rgb)> {
+    osc_replies(s)
+        .filter_map(|reply| {
+            let (index, raw_color) = reply.strip_prefix("]4;")?.split_once(';')?;
+            Some((index.parse().ok()?, parse_rgba(raw_color).ok()?.0))
+        })
+        .collect()
+}
+
+/// Parse a color given as `rgb:<red>/<green>/<blue>`, or as
+/// `rgba:<red>/<green>/<blue>/<alpha>` (sent by some terminals with
+/// a transparent background), with the parts being each 1 to 4 hex
+/// digits (usually 4), or as `#<red><green><blue>`.
+///
+/// The alpha is 255 (opaque) when not given.
+pub(crate) fn parse_rgba(s: &str) -> Result<(Rgb, u8), TlError> {
+    let channels: Vec<u8> = raw_channels(s)
+        .ok_or_else(|| TlError::UnparsableResponse(s.to_string()))?
+        .into_iter()
+        .map(scale_channel)
+        .collect();
+    let alpha = channels.get(3).copied().unwrap_or(255);
+    Ok((Rgb::new(channels[0], channels[1], channels[2]), alpha))
+}
+
+/// Split a color into its channels (including the alpha, if given), each
+/// being 1 to 4 hex digits.
+///
+/// A color missing a channel, or with an empty one, is rejected rather
+/// than completed with a guess: it's probably truncated.
+fn raw_channels(s: &str) -> Option<Vec<&str>> {
+    let channels = if let Some(raw_color) = s.strip_prefix("rgba:") {
+        split_channels(raw_color, 4)?
+    } else if let Some(raw_color) = s.strip_prefix("rgb:") {
+        split_channels(raw_color, 3)?
+    } else {
+        hex_channels(s.strip_prefix('#')?)?
+    };
+    let valid = |c: &str| (1..=4).contains(&c.len()) && c.bytes().all(|b| b.is_ascii_hexdigit());
+    channels.iter().all(|c| valid(c)).then_some(channels)
+}
+
+/// Split the given number of channels separated by slashes
+fn split_channels(raw_color: &str, count: usize) -> Option<Vec<&str>> {
+    let channels: Vec<&str> = raw_color.split('/').collect();
+    (channels.len() == count).then_some(channels)
+}
+
+/// Split the channels of a color given in the older X11 format, which is
+/// `#RGB`, `#RRGGBB`, `#RRRGGGBBB`, or `#RRRRGGGGBBBB`
+fn hex_channels(hex: &str) -> Option<Vec<&str>> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(3) {
+        return None;
+    }
+    let n = hex.len() / 3;
+    Some((0..3).map(|i| &hex[i * n..(i + 1) * n]).collect())
+}
+
+/// Parse a valid channel of 1 to 4 hex digits, keeping its 8 most
+/// significant bits
+fn scale_channel(s: &str) -> u8 {
+    let value = u16::from_str_radix(s, 16).unwrap_or_default();
+    (match s.len() {
+        1 => value * 0x11,
+        2 => value,
+        3 => value >> 4,
+        _ => value >> 8,
+    }) as u8
+}
+
+#[test]
+fn test_parse_color_reply() {
+    assert_eq!(
+        parse_color_reply(11, "\x1b]11;rgb:3838/a4a4/c9c9\x1b\\")
+            .unwrap()
+            .0,
+        Rgb::new(0x38, 0xa4, 0xc9),
+    );
+    assert_eq!(
+        parse_color_reply(10, "\x1b]10;rgb:ffff/ffff/ffff\x07")
+            .unwrap()
+            .0,
+        Rgb::new(255, 255, 255),
+    );
+    // answer to DA1 before the OSC reply
+    assert_eq!(
+        parse_color_reply(11, "\x1b[?62;22c\x1b]11;rgb:0000/2b2b/3636\x07")
+            .unwrap()
+            .0,
+        Rgb::new(0, 0x2b, 0x36),
+    );
+    assert!(matches!(
+        parse_color_reply(10, "\x1b]11;rgb:ffff/ffff/ffff\x07"),
+        Err(TlError::NotAnswered),
+    ));
+    assert_eq!(
+        parse_color_reply(12, "\x1b]12;rgb:ffff/cccc/0000\x07")
+            .unwrap()
+            .0,
+        Rgb::new(0xff, 0xcc, 0),
+    );
+    assert!(matches!(
+        parse_color_reply(11, ""),
+        Err(TlError::NotAnswered),
+    ));
+    // shorter channels are scaled to 8 bits
+    assert_eq!(
+        parse_color_reply(11, "\x1b]11;rgb:f/80/123\x07").unwrap().0,
+        Rgb::new(0xff, 0x80, 0x12),
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_parse_color_reply_16bit() {
+    assert_eq!(
+        parse_color_reply_16bit(11, "\x1b]11;rgb:3839/a4a4/c9c0\x07").unwrap(),
+        (0x3839, 0xa4a4, 0xc9c0),
+    );
+    // shorter channels are zero-extended, not scaled
+    assert_eq!(
+        parse_color_reply_16bit(11, "\x1b]11;rgb:ff/80/1\x07").unwrap(),
+        (0xff, 0x80, 0x1),
+    );
+    assert!(matches!(
+        parse_color_reply_16bit(11, "\x1b]11;rgb:ffff/ffff\x07"),
+        Err(TlError::UnparsableResponse(_)),
+    ));
+}
+
+#[test]
+fn test_parse_rgba() {
+    assert_eq!(
+        parse_color_reply(11, "\x1b]11;rgba:1e1e/1e1e/2e2e/cccc\x1b\\")
+            .unwrap()
+            .0,
+        Rgb::new(0x1e, 0x1e, 0x2e),
+    );
+    assert_eq!(
+        parse_rgba("rgba:1e1e/1e1e/2e2e/cccc").unwrap(),
+        (Rgb::new(0x1e, 0x1e, 0x2e), 0xcc),
+    );
+    assert_eq!(
+        parse_rgba("rgb:1e1e/1e1e/2e2e").unwrap(),
+        (Rgb::new(0x1e, 0x1e, 0x2e), 255),
+    );
+    assert!(parse_rgba("rgba:1e1e/1e1e/2e2e").is_err());
+    assert!(parse_rgba("rgb:1e1e/1e1e/2e2e/cccc").is_err());
+}
+
+#[test]
+fn test_parse_truncated_reply() {
+    for answer in [
+        "\x1b]11;rgb:ff",
+        "\x1b]11;rgb:ffff/ffff",
+        "\x1b]11;rgb:ffff/ffff/",
+        "\x1b]11;rgb:ffff//ffff\x07",
+        "\x1b]11;rgb:\x07",
+    ] {
+        assert!(
+            matches!(
+                parse_color_reply(11, answer),
+                Err(TlError::UnparsableResponse(_))
+            ),
+            "{answer:?} should be rejected",
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_parse_palette_replies() {
+    let s = "\x1b]4;0;rgb:0000/0000/0000\x07\x1b]4;12;rgb:7272/9f9f/cfcf\x1b\\";
+    assert_eq!(
+        parse_palette_replies(s),
+        vec![(0, Rgb::new(0, 0, 0)), (12, Rgb::new(0x72, 0x9f, 0xcf))],
+    );
+    // a truncated reply is ignored
+    let s = "\x1b]4;3;rgb:c4c4/a0a0/0000\x07\x1b]4;4;rgb:34";
+    assert_eq!(parse_palette_replies(s), vec![(3, Rgb::new(0xc4, 0xa0, 0))]);
+}
+
+/// Answers of terminals to the background color query, with the
+/// color they should be parsed as
+#[cfg(test)]
+const CORPUS: &[(&str, &[u8], Rgb)] = &[
+    (
+        "4 digits, BEL terminated (xterm, iTerm2, Alacritty)",
+        b"\x1b]11;rgb:ffff/ffff/dddd\x07",
+        Rgb::new(0xff, 0xff, 0xdd),
+    ),
+    (
+        "4 digits, ST terminated (VTE, kitty)",
+        b"\x1b]11;rgb:2828/2c2c/3434\x1b\\",
+        Rgb::new(0x28, 0x2c, 0x34),
+    ),
+    (
+        "2 digits",
+        b"\x1b]11;rgb:1d/1f/21\x07",
+        Rgb::new(0x1d, 0x1f, 0x21),
+    ),
+    (
+        "alpha channel (rxvt-unicode with transparency)",
+        b"\x1b]11;rgba:0000/2b2b/3636/e6e6\x07",
+        Rgb::new(0, 0x2b, 0x36),
+    ),
+    (
+        "X11 #hex",
+        b"\x1b]11;#282828\x07",
+        Rgb::new(0x28, 0x28, 0x28),
+    ),
+    (
+        "X11 #hex, 4 digits",
+        b"\x1b]11;#ffffeeeedddd\x1b\\",
+        Rgb::new(0xff, 0xee, 0xdd),
+    ),
+    (
+        "repeated code",
+        b"\x1b]11;11;rgb:2e2e/3434/4040\x07",
+        Rgb::new(0x2e, 0x34, 0x40),
+    ),
+    (
+        "extra fields",
+        b"\x1b]11;0;x;#2e3440\x1b\\",
+        Rgb::new(0x2e, 0x34, 0x40),
+    ),
+    (
+        "echoed query",
+        b"\x1b]11;?\x07\x1b]11;rgb:0000/0000/0000\x07",
+        Rgb::new(0, 0, 0),
+    ),
+    (
+        "DA1 answer first",
+        b"\x1b[?62;22c\x1b]11;rgb:fdfd/f6f6/e3e3\x07",
+        Rgb::new(0xfd, 0xf6, 0xe3),
+    ),
+    (
+        "keypresses around the reply",
+        b"q\x1b]11;rgb:2828/2c2c/3434\x07j",
+        Rgb::new(0x28, 0x2c, 0x34),
+    ),
+    (
+        "8-bit C1 introducer and terminator",
+        b"\x9d11;rgb:1e1e/1e1e/2e2e\x9c",
+        Rgb::new(0x1e, 0x1e, 0x2e),
+    ),
+    (
+        "UTF-8 encoded C1 introducer and terminator",
+        b"\xc2\x9d11;rgb:1e1e/1e1e/2e2e\xc2\x9c",
+        Rgb::new(0x1e, 0x1e, 0x2e),
+    ),
+];
+
+#[test]
+fn test_corpus() {
+    for &(format, bytes, expected) in CORPUS {
+        match parse_reply(bytes) {
+            Ok(color) => assert_eq!(color.rgb(), expected, "wrong color for {format}"),
+            Err(e) => panic!("{format} not parsed: {e}"),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_strip_replies() {
+    assert_eq!(strip_replies("q\x1b]11;rgb:2828/2c2c/3434\x07j"), "qj");
+    assert_eq!(strip_replies("\x1b]11;rgb:0000/0000/0000\x1b\\k"), "k");
+    assert_eq!(strip_replies("\x1b[?62;22c\x1b[A\x1b]11;?\x07"), "\x1b[A");
+    assert_eq!(strip_replies("\x1b]11;rgb:00"), "");
+    assert_eq!(strip_replies("\x1b]11;rgb:00\x1b[A"), "\x1b[A");
+    assert_eq!(strip_replies(""), "");
+    // the late answer to the fence of a previous query
+    assert_eq!(strip_replies("\x1b]11;rgb:0000/0000/0000\x07\x1b[0n"), "");
+    assert_eq!(strip_replies("\x1b[3nq"), "q");
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(decode(b"\x9b0n"), "\x1b[0n");
+    assert_eq!(decode(b"\xc2\x9d11;"), "\x1b]11;");
+    // not a C1 control but part of another char
+    assert_eq!(decode("é\u{1d}".as_bytes()), "é\u{1d}");
+    assert_eq!(decode("\u{75d}".as_bytes()), "\u{75d}");
+}
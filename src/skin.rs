@@ -0,0 +1,79 @@
+use {
+    crate::*,
+    std::ops::{Bound, RangeBounds},
+};
+
+/// A range of luma, with its start and end bounds
+type LumaRange = (Bound<f32>, Bound<f32>);
+
+/// A set of skins (themes, color sets, etc.) registered with the
+/// ranges of background luma they're suited to, with a default one
+/// for the other cases.
+///
+/// ```
+/// let skins = terminal_light::SkinSet::new("medium")
+///     .add(..0.2, "dark")
+///     .add(0.2..0.5, "dim")
+///     .add(0.85.., "light");
+/// let skin: &&str = skins.select();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SkinSet<T> {
+    skins: Vec<(LumaRange, T)>,
+    default: T,
+}
+
+impl<T> SkinSet<T> {
+    /// Create a set with only the skin to use when the luma is unknown,
+    /// or no other skin or several ones match it
+    pub fn new(default: T) -> Self {
+        Self {
+            skins: Vec::new(),
+            default,
+        }
+    }
+    /// Register a skin for the given range of luma
+    pub fn add<R: RangeBounds<f32>>(mut self, lumas: R, skin: T) -> Self {
+        let bounds = (lumas.start_bound().cloned(), lumas.end_bound().cloned());
+        self.skins.push((bounds, skin));
+        self
+    }
+    /// Return the skin matching the given luma, or the default one
+    /// when there's not exactly one
+    pub fn for_luma(&self, luma: f32) -> &T {
+        let mut matching = self
+            .skins
+            .iter()
+            .filter(|(lumas, _)| lumas.contains(&luma))
+            .map(|(_, skin)| skin);
+        match (matching.next(), matching.next()) {
+            (Some(skin), None) => skin,
+            _ => &self.default,
+        }
+    }
+    /// Determine the luma of the terminal's background (see [luma]) and
+    /// return the matching skin, or the default one
+    pub fn select(&self) -> &T {
+        match luma() {
+            Ok(luma) => self.for_luma(luma),
+            Err(_) => &self.default,
+        }
+    }
+}
+
+#[test]
+fn test_skin_set_for_luma() {
+    let skins = SkinSet::new("default")
+        .add(..0.2, "dark")
+        .add(0.2..0.5, "dim")
+        .add(0.4..=0.6, "grey")
+        .add(0.85.., "light");
+    assert_eq!(*skins.for_luma(0.0), "dark");
+    assert_eq!(*skins.for_luma(0.2), "dim");
+    assert_eq!(*skins.for_luma(0.55), "grey");
+    assert_eq!(*skins.for_luma(1.0), "light");
+    // overlap
+    assert_eq!(*skins.for_luma(0.45), "default");
+    // miss
+    assert_eq!(*skins.for_luma(0.7), "default");
+}
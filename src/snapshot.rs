@@ -0,0 +1,90 @@
+use crate::*;
+
+/// The colors of the terminal, as obtained with a single round trip
+/// by [full_snapshot].
+///
+/// Each color is `None` when the terminal didn't answer its query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FullSnapshot {
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+    pub cursor: Option<Rgb>,
+    /// The 16 standard ANSI colors, as displayed by the terminal
+    pub palette: [Option<Rgb>; 16],
+}
+
+impl FullSnapshot {
+    /// Parse everything the terminal answered to the snapshot queries
+    #[cfg(unix)]
+    pub(crate) fn from_answer(answer: &str) -> Self {
+        let color = |code| {
+            reply::parse_color_reply(code, answer)
+                .ok()
+                .map(|(rgb, _)| rgb)
+        };
+        let mut palette = [None; 16];
+        for (index, rgb) in reply::parse_palette_replies(answer) {
+            if let Some(entry) = palette.get_mut(index as usize) {
+                entry.get_or_insert(rgb);
+            }
+        }
+        Self {
+            foreground: color(10),
+            background: color(11),
+            cursor: color(12),
+            palette,
+        }
+    }
+    /// Tell whether the terminal answered all the queries
+    pub fn is_complete(&self) -> bool {
+        self.foreground.is_some()
+            && self.background.is_some()
+            && self.cursor.is_some()
+            && self.palette.iter().all(Option::is_some)
+    }
+    /// Tell whether the terminal answered none of the queries
+    #[cfg(unix)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.foreground.is_none()
+            && self.background.is_none()
+            && self.cursor.is_none()
+            && self.palette.iter().all(Option::is_none)
+    }
+}
+
+/// Query the foreground, background and cursor colors of the terminal,
+/// and its 16 colors palette, with a single round trip.
+///
+/// Times out after 100ms (see [set_default_timeout]).
+///
+/// There's no fallback here: only the colors the terminal answered are
+/// given, and a `TlError::NotAnswered` error is returned when it answered
+/// none of them.
+///
+/// ```
+/// if let Ok(snapshot) = terminal_light::full_snapshot() {
+///     if let Some(red) = snapshot.palette[1] {
+///         println!("the terminal's red is {red:?}");
+///     }
+/// }
+/// ```
+pub fn full_snapshot() -> Result<FullSnapshot, TlError> {
+    Query::new().full_snapshot()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_snapshot_from_answer() {
+    let answer = "\x1b]10;rgb:ffff/ffff/ffff\x07\x1b]11;rgb:2828/2c2c/3434\x07\
+        \x1b]4;1;rgb:cdcd/0000/0000\x07\x1b]4;15;#fff\x07";
+    let snapshot = FullSnapshot::from_answer(answer);
+    assert_eq!(snapshot.foreground, Some(Rgb::new(255, 255, 255)));
+    assert_eq!(snapshot.background, Some(Rgb::new(0x28, 0x2c, 0x34)));
+    assert_eq!(snapshot.cursor, None);
+    assert_eq!(snapshot.palette[1], Some(Rgb::new(0xcd, 0, 0)));
+    assert_eq!(snapshot.palette[15], Some(Rgb::new(255, 255, 255)));
+    assert_eq!(snapshot.palette[0], None);
+    assert!(!snapshot.is_complete());
+    assert!(!snapshot.is_empty());
+    assert!(FullSnapshot::from_answer("").is_empty());
+}
@@ -0,0 +1,70 @@
+//! Counters of the detections and of their outcomes, reported to the
+//! recorder of the `metrics` crate when the `metrics` feature is enabled.
+//!
+//! Without the feature, recording is a no-op.
+
+use crate::*;
+
+/// Something worth counting in a detection of the background
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Event {
+    Attempt,
+    #[cfg(unix)]
+    OscSuccess,
+    EnvSuccess,
+    Timeout,
+    ParseFailure,
+}
+
+impl Event {
+    #[cfg(feature = "metrics")]
+    fn counter_name(self) -> &'static str {
+        match self {
+            Self::Attempt => "terminal_light_attempts",
+            #[cfg(unix)]
+            Self::OscSuccess => "terminal_light_osc_successes",
+            Self::EnvSuccess => "terminal_light_env_successes",
+            Self::Timeout => "terminal_light_timeouts",
+            Self::ParseFailure => "terminal_light_parse_failures",
+        }
+    }
+}
+
+/// Increment the counter of the event, tagged with the multiplexer
+/// and the terminal program
+#[cfg(feature = "metrics")]
+pub(crate) fn record(event: Event) {
+    let term_program = env::term_program().unwrap_or_else(|| "unknown".to_string());
+    metrics::counter!(
+        event.counter_name(),
+        "multiplexer" => multiplexer(),
+        "term_program" => term_program,
+    )
+    .increment(1);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record(_event: Event) {}
+
+/// Record the failure of a strategy, when it's one we count
+pub(crate) fn record_failure(err: &TlError) {
+    match err {
+        TlError::Timeout => record(Event::Timeout),
+        TlError::UnparsableResponse(_) | TlError::WrongFormat(_) | TlError::ParseInt(_) => {
+            record(Event::ParseFailure)
+        }
+        _ => {}
+    }
+}
+
+/// Return the name of the terminal multiplexer we're running in
+#[cfg(feature = "metrics")]
+fn multiplexer() -> &'static str {
+    if env::var("TMUX").is_some() {
+        "tmux"
+    } else if env::var("STY").is_some() {
+        "screen"
+    } else {
+        "none"
+    }
+}
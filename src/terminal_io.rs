@@ -0,0 +1,141 @@
+use std::{
+    fmt, io,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+#[cfg(unix)]
+use {
+    crate::tty,
+    std::sync::MutexGuard,
+    std::{
+        io::{Read, Write},
+        os::fd::AsFd,
+    },
+};
+
+/// A transport of the queries to the terminal and of its answers, to use
+/// instead of the process' tty (an in-process terminal emulator, a network
+/// console, a test double, etc.).
+///
+/// Give it to a query with [Query::terminal_io](crate::Query::terminal_io).
+/// On unix, a socket or another non tty stream can be given through
+/// a `StreamIo`.
+pub trait TerminalIo: Send {
+    /// Send escape sequences to the terminal
+    fn write_query(&mut self, query: &[u8]) -> io::Result<()>;
+    /// Return the bytes received from the terminal, waiting for them at
+    /// most until the deadline (an empty vec means nothing came in time).
+    ///
+    /// An error of kind [UnexpectedEof](io::ErrorKind::UnexpectedEof) tells
+    /// the terminal is gone, and makes the query fail with
+    /// [TlError::Disconnected](crate::TlError::Disconnected).
+    fn read_reply(&mut self, deadline: Instant) -> io::Result<Vec<u8>>;
+    /// Prepare the terminal to answer, like the switch to raw mode
+    /// of a tty
+    fn enter_raw(&mut self) -> io::Result<()>;
+    /// Restore the terminal as it was before [enter_raw](Self::enter_raw)
+    fn restore(&mut self) -> io::Result<()>;
+}
+
+/// A [TerminalIo] over a stream which isn't a tty, like the Unix socket
+/// of a terminal proxy or broker.
+///
+/// The answers are read with the timeout of the query, but there's no
+/// raw mode for a socket: [enter_raw](TerminalIo::enter_raw) and
+/// [restore](TerminalIo::restore) do nothing, the other end being
+/// responsible for the settings of the real terminal.
+///
+/// ```no_run
+/// use {std::os::unix::net::UnixStream, terminal_light::*};
+///
+/// let stream = UnixStream::connect("/run/term-broker.sock")?;
+/// let bg = Query::new()
+///     .terminal_io(Box::new(StreamIo::new(stream)))
+///     .background_color()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct StreamIo<S> {
+    stream: S,
+}
+
+#[cfg(unix)]
+impl<S: Read + Write + AsFd + Send> StreamIo<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream }
+    }
+    /// Return the wrapped stream
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+#[cfg(unix)]
+impl<S: Read + Write + AsFd + Send> TerminalIo for StreamIo<S> {
+    fn write_query(&mut self, query: &[u8]) -> io::Result<()> {
+        self.stream.write_all(query)?;
+        self.stream.flush()
+    }
+    fn read_reply(&mut self, deadline: Instant) -> io::Result<Vec<u8>> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if !tty::wait_for_input(self.stream.as_fd(), remaining).map_err(io::Error::from)? {
+            return Ok(Vec::new());
+        }
+        let mut buffer = [0; 256];
+        match self.stream.read(&mut buffer)? {
+            0 => Err(io::ErrorKind::UnexpectedEof.into()),
+            n => Ok(buffer[..n].to_vec()),
+        }
+    }
+    fn enter_raw(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    fn restore(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A [TerminalIo] which can be shared between clones of a query
+/// (it's only used on unix, the only platform where the terminal is queried)
+#[derive(Clone)]
+pub(crate) struct SharedIo(
+    #[cfg_attr(not(unix), allow(dead_code))] Arc<Mutex<Box<dyn TerminalIo>>>,
+);
+
+impl SharedIo {
+    pub(crate) fn new(io: Box<dyn TerminalIo>) -> Self {
+        Self(Arc::new(Mutex::new(io)))
+    }
+    #[cfg(unix)]
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Box<dyn TerminalIo>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for SharedIo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TerminalIo")
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stream_io() {
+    use {crate::*, std::os::unix::net::UnixStream};
+    let (ours, mut proxy) = UnixStream::pair().unwrap();
+    let proxy = std::thread::spawn(move || {
+        let mut query = [0; 64];
+        let n = proxy.read(&mut query).unwrap();
+        proxy
+            .write_all(b"\x1b]11;rgb:fdfd/f6f6/e3e3\x1b\\\x1b[0n")
+            .unwrap();
+        query[..n].to_vec()
+    });
+    let bg = Query::new()
+        .terminal_io(Box::new(StreamIo::new(ours)))
+        .background_color()
+        .unwrap();
+    assert_eq!(bg.rgb(), Rgb::new(0xfd, 0xf6, 0xe3));
+    assert_eq!(proxy.join().unwrap(), b"\x1b]11;?\x07\x1b[5n");
+}
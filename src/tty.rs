@@ -0,0 +1,641 @@
+use crate::*;
+use nix::{
+    sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg, SpecialCharacterIndices, Termios},
+    unistd::{getpgrp, tcgetpgrp},
+};
+use std::{
+    fs::File,
+    io::{self, IsTerminal, Read, Write},
+    os::fd::{AsFd, BorrowedFd},
+    sync::{Mutex, OnceLock},
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+/// The Status Report query we send after the real queries, as a fence.
+///
+/// Almost all terminals support it, even if they don't support the
+/// queries before, so we can detect "not supported" by the Status Report
+/// being answered first.
+const FENCE: &str = "\x1b[5n";
+
+/// Tell whether we can open the tty and change its termios settings,
+/// which the query round trip requires.
+///
+/// The probe is done only once: in a restricted environment (seccomp,
+/// some containers) we don't want to retry the forbidden syscalls on
+/// every query.
+///
+/// A background process can't change the termios settings without being
+/// stopped by SIGTTOU, so it's told it can't query the terminal, without
+/// probing and without caching this answer.
+pub fn can_query_terminal() -> bool {
+    static CAN_QUERY: OnceLock<bool> = OnceLock::new();
+    is_foreground() && *CAN_QUERY.get_or_init(probe_terminal)
+}
+
+fn probe_terminal() -> bool {
+    use crossterm::terminal::*;
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty");
+    if tty.is_err() {
+        return false;
+    }
+    match is_raw_mode_enabled() {
+        Ok(true) => true, // termios were already successfully set
+        Ok(false) => enable_raw_mode().is_ok() && disable_raw_mode().is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Run the given function with the terminal in raw mode, restoring
+/// the previous mode after.
+///
+/// When the query [assumes raw mode](Query::assume_raw), the terminal
+/// settings aren't touched at all. When it has its own [transport](Query::terminal_io),
+/// this transport is prepared instead of the tty.
+pub fn with_raw_mode<T, F>(options: &Query, f: F) -> Result<T, TlError>
+where
+    F: FnOnce() -> Result<T, TlError>,
+{
+    use crossterm::terminal::*;
+    if let Some(io) = &options.io {
+        io.lock().enter_raw()?;
+        let res = f();
+        io.lock().restore()?;
+        return res;
+    }
+    if options.assume_raw {
+        if !may_write_queries() || !is_foreground() {
+            return Err(TlError::TerminalNotQueryable);
+        }
+        return f();
+    }
+    // can_query_terminal also checks we're in the foreground, as a background
+    // process changing the termios settings would be stopped by SIGTTOU
+    if !may_write_queries() || !can_query_terminal() {
+        return Err(TlError::TerminalNotQueryable);
+    }
+    // The tty may have been hung up or revoked since the check, so the
+    // termios calls may still fail
+    let switch_to_raw = !is_raw_mode_enabled().map_err(TlError::TerminalAttributes)?;
+    if switch_to_raw {
+        enable_raw_mode().map_err(TlError::TerminalAttributes)?;
+    }
+    let res = f();
+    if switch_to_raw {
+        disable_raw_mode().map_err(TlError::TerminalAttributes)?;
+    }
+    res
+}
+
+/// Tell whether our process group is the foreground one of the tty.
+///
+/// A background process writing to the tty, or changing its settings,
+/// gets stopped by SIGTTOU (when the tty has `tostop`, for the writes),
+/// so we don't query the terminal from the background.
+fn is_foreground() -> bool {
+    File::open("/dev/tty").is_ok_and(|tty| is_foreground_of(tty.as_fd()))
+}
+
+fn is_foreground_of(fd: BorrowedFd<'_>) -> bool {
+    tcgetpgrp(fd).is_ok_and(|pgrp| pgrp == getpgrp())
+}
+
+/// Check the termios attributes of the fd can still be read, that is
+/// that it's still a terminal, and that it's in non canonical mode.
+///
+/// In canonical mode, the terminal's answer would be line buffered and
+/// stay at the prompt until the user hits Enter, so the queries must not
+/// be written, whether the switch to raw mode failed or was skipped.
+fn check_termios(fd: BorrowedFd<'_>) -> Result<(), TlError> {
+    let termios = tcgetattr(fd).map_err(|e| TlError::TerminalAttributes(e.into()))?;
+    if termios.local_flags.contains(LocalFlags::ICANON) {
+        return Err(TlError::TerminalNotQueryable);
+    }
+    Ok(())
+}
+
+/// Write the given queries to stdout, followed by the Status Report
+/// fence, and return the tty on which the answers will come.
+///
+/// Everything which may fail is done before writing, so that we never
+/// emit escape sequences that we aren't ready to read the answer of.
+pub fn send_queries(queries: &str) -> Result<File, TlError> {
+    send(&format!("{queries}{FENCE}"))
+}
+
+/// Write the given escape sequences to stdout, and return the tty on
+/// which the answers will come
+pub fn send(sequences: &str) -> Result<File, TlError> {
+    if !may_write_queries() {
+        return Err(TlError::TerminalNotQueryable);
+    }
+    let term = env::var("TERM").ok_or(TlError::Unsupported)?;
+    if term == "dumb" {
+        return Err(TlError::Unsupported);
+    }
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    check_termios(tty.as_fd())?;
+    if !is_foreground_of(tty.as_fd()) {
+        return Err(TlError::TerminalNotQueryable);
+    }
+    // pending input can't be an answer to our queries, and would
+    // be mistaken for one
+    drain_pending_input(&tty)?;
+    let message = wrap_for_multiplexer(sequences, &term);
+    if io::stdout().is_terminal() {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(message.as_bytes())?;
+        stdout.flush()?;
+    } else {
+        // colors are forced while the output goes to a pager or a file,
+        // the queries mustn't end there
+        (&tty).write_all(message.as_bytes())?;
+    }
+    Ok(tty)
+}
+
+/// Read what's already waiting on the tty, keeping the keys the user typed
+/// for [take_pending_input] (the late answers to previous queries are
+/// dropped)
+fn drain_pending_input(tty: &File) -> Result<(), TlError> {
+    let mut bytes = Vec::new();
+    let mut buffer = [0; 256];
+    while wait_for_input(tty.as_fd(), Duration::ZERO)? {
+        match read_tty(tty, &mut buffer)? {
+            0 => break,
+            n => bytes.extend_from_slice(&buffer[..n]),
+        }
+    }
+    keep_pending_input(reply::strip_replies(&reply::decode(&bytes)).as_bytes());
+    Ok(())
+}
+
+/// Tell whether the queries may be written: when stdin and stdout are
+/// a terminal, or when colors are forced with `$CLICOLOR_FORCE`
+pub(crate) fn may_write_queries() -> bool {
+    is_interactive_terminal() || env::is_color_forced()
+}
+
+/// Wrap the message, if needed, so that the terminal multiplexer
+/// we're running in forwards it to the terminal
+fn wrap_for_multiplexer(message: &str, term: &str) -> String {
+    // `$TERM` alone isn't enough: it's often set to `screen-256color` in
+    // tmux or outside of any multiplexer, while `$STY` is only set by Screen
+    if term.starts_with("screen") && env::var("STY").is_some() {
+        // Running under GNU Screen, the queries must be wrapped in a
+        // "Device Control String" for Screen to forward them
+        format!("\x1bP{message}\x1b\\")
+    } else {
+        message.to_string()
+    }
+}
+
+/// The answer of the terminal to queries followed by the fence,
+/// accumulated as it's received
+#[derive(Debug, Default)]
+pub struct FencedAnswer {
+    bytes: Vec<u8>,
+}
+
+impl FencedAnswer {
+    /// Tell whether some bytes were received
+    pub fn is_started(&self) -> bool {
+        !self.bytes.is_empty()
+    }
+    /// Add received bytes, and return the answer to the queries
+    /// (everything before the answer to the fence) if it's complete
+    ///
+    /// The keys the user typed meanwhile are kept for [take_pending_input].
+    pub fn feed(&mut self, bytes: &[u8]) -> Option<String> {
+        self.bytes.extend_from_slice(bytes);
+        let (answer, input) = split_fenced(&self.bytes)?;
+        keep_pending_input(input.as_bytes());
+        Some(answer)
+    }
+    /// Keep, for [take_pending_input], the keys typed during a query
+    /// which ended without the answer to the fence
+    pub(crate) fn keep_unfenced_input(&self) {
+        keep_pending_input(self.unfenced_input().as_bytes());
+    }
+    /// Return the keys typed during a query which ended without the
+    /// answer to the fence
+    fn unfenced_input(&self) -> String {
+        reply::strip_replies(&reply::decode(&self.bytes))
+    }
+}
+
+/// Split the received bytes, when they contain the answer to the fence,
+/// into the answer to the queries and the keys typed by the user
+///
+/// The bytes after the fence may hold late replies to a previous attempt,
+/// which are stripped too.
+fn split_fenced(bytes: &[u8]) -> Option<(String, String)> {
+    // The Status Report is answered with either `ESC[0n` (ok)
+    // or `ESC[3n` (malfunction), possibly with an 8-bit CSI
+    let mut answer = reply::decode(bytes);
+    let fence = answer
+        .find("\x1b[0n")
+        .into_iter()
+        .chain(answer.find("\x1b[3n"))
+        .min()?;
+    let after = answer.split_off(fence);
+    let mut input = reply::strip_replies(&answer);
+    input.push_str(&reply::strip_replies(&after[4..]));
+    Some((answer, input))
+}
+
+/// The keys typed by the user during the queries, which were read
+/// with the answers
+static PENDING_INPUT: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+pub(crate) fn keep_pending_input(input: &[u8]) {
+    if !input.is_empty() {
+        let mut pending = PENDING_INPUT.lock().unwrap_or_else(|e| e.into_inner());
+        pending.extend_from_slice(input);
+    }
+}
+
+/// Return, and forget, the keys typed by the user during the queries
+pub fn take_pending_input() -> Vec<u8> {
+    let mut pending = PENDING_INPUT.lock().unwrap_or_else(|e| e.into_inner());
+    std::mem::take(&mut *pending)
+}
+
+/// When to stop waiting for the answer of the terminal
+#[derive(Debug, Clone, Copy)]
+pub struct Deadlines {
+    /// Limit for the terminal to start answering (the answer to the
+    /// fence comes fast, even when the queries aren't supported)
+    pub first_bytes: Instant,
+    /// Limit for the whole answer
+    pub complete: Instant,
+}
+
+impl Deadlines {
+    /// Compute the deadlines of a query starting now
+    pub fn new(options: &Query) -> Self {
+        let now = Instant::now();
+        Self {
+            first_bytes: now + options.barrier_timeout.unwrap_or(options.timeout),
+            complete: now + options.timeout,
+        }
+    }
+    /// Return the deadline to consider, depending on whether the
+    /// terminal started answering
+    pub fn current(self, started: bool) -> Instant {
+        if started {
+            self.complete
+        } else {
+            self.first_bytes.min(self.complete)
+        }
+    }
+}
+
+/// Read the tty, passing the received bytes to the `feed` function,
+/// until it's ready with a result or the deadlines are reached.
+///
+/// The timeouts are normally handled by the tty driver, with the `VTIME`
+/// setting, which spares a syscall per read. When the tty settings can't
+/// or mustn't be changed (see `use_vtime`), and for the waits shorter than
+/// the 100ms step of `VTIME`, we wait with `poll` (or `select`) instead.
+pub fn read_until<T, F>(
+    tty: &File,
+    deadlines: Deadlines,
+    use_vtime: bool,
+    mut feed: F,
+) -> Result<T, TlError>
+where
+    F: FnMut(&[u8]) -> Poll<Result<T, TlError>>,
+{
+    let mut vtime = if use_vtime {
+        VtimeMode::new(tty.as_fd()).ok()
+    } else {
+        None
+    };
+    let mut buffer = [0; 256];
+    let mut started = false;
+    loop {
+        let deadline = deadlines.current(started);
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(TlError::Timeout);
+        }
+        let bytes_read = match vtime.as_mut() {
+            // VTIME counts in tenths of second, shorter waits and the
+            // remainder of longer ones are done with poll
+            Some(vtime) if remaining >= VTIME_STEP => {
+                vtime.set_timeout(remaining)?;
+                match read_tty(tty, &mut buffer)? {
+                    // A hung up tty stays readable, its reads returning 0 at once,
+                    // while an expired VTIME leaves it with nothing to read
+                    0 if wait_for_input(tty.as_fd(), Duration::ZERO)? => {
+                        match read_tty(tty, &mut buffer)? {
+                            0 => return Err(TlError::Disconnected),
+                            n => n,
+                        }
+                    }
+                    0 => continue, // VTIME expired, the deadline is checked again
+                    n => n,
+                }
+            }
+            _ => {
+                if !wait_for_input(tty.as_fd(), remaining)? {
+                    return Err(TlError::Timeout);
+                }
+                match read_tty(tty, &mut buffer)? {
+                    0 => return Err(TlError::Disconnected),
+                    n => n,
+                }
+            }
+        };
+        started = true;
+        if let Poll::Ready(res) = feed(&buffer[..bytes_read]) {
+            return res;
+        }
+    }
+}
+
+/// Read the tty, the EIO error of a tty whose session ended being
+/// reported as a disconnection
+pub(crate) fn read_tty(tty: &File, buffer: &mut [u8]) -> Result<usize, TlError> {
+    (&*tty).read(buffer).map_err(|e| match e.raw_os_error() {
+        Some(nix::libc::EIO) => TlError::Disconnected,
+        _ => e.into(),
+    })
+}
+
+/// The tty settings for reads returning after a timeout (`VMIN=0`
+/// and `VTIME` in tenths of seconds), the previous settings being
+/// restored on drop.
+///
+/// The terminal must be in non canonical mode for them to apply.
+struct VtimeMode<'f> {
+    fd: BorrowedFd<'f>,
+    previous: Termios,
+    current: Termios,
+}
+
+impl<'f> VtimeMode<'f> {
+    fn new(fd: BorrowedFd<'f>) -> nix::Result<Self> {
+        let previous = tcgetattr(fd)?;
+        Ok(Self {
+            fd,
+            current: previous.clone(),
+            previous,
+        })
+    }
+    /// Set the timeout of the next reads
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), TlError> {
+        let vtime = vtime(timeout);
+        let cc = &mut self.current.control_chars;
+        let (vmin_index, vtime_index) = (
+            SpecialCharacterIndices::VMIN as usize,
+            SpecialCharacterIndices::VTIME as usize,
+        );
+        if cc[vmin_index] == 0 && cc[vtime_index] == vtime {
+            return Ok(());
+        }
+        cc[vmin_index] = 0;
+        cc[vtime_index] = vtime;
+        tcsetattr(self.fd, SetArg::TCSANOW, &self.current)
+            .map_err(|e| TlError::TerminalAttributes(e.into()))
+    }
+}
+
+/// The unit of the `VTIME` setting
+const VTIME_STEP: Duration = Duration::from_millis(100);
+
+/// Return the `VTIME` value for a timeout, that is the timeout in tenths
+/// of second, rounded down so that the read never ends after the deadline,
+/// and between 0.1s and 25.5s
+fn vtime(timeout: Duration) -> u8 {
+    (timeout.as_millis() / VTIME_STEP.as_millis()).clamp(1, 255) as u8
+}
+
+impl Drop for VtimeMode<'_> {
+    fn drop(&mut self) {
+        let _ = tcsetattr(self.fd, SetArg::TCSANOW, &self.previous);
+    }
+}
+
+/// Send the given queries followed by the fence, and return everything
+/// the terminal answered before the fence, assuming the terminal is in
+/// raw mode.
+///
+/// Only one round trip is needed, whatever the number of queries.
+pub fn query_fenced(queries: &str, options: &Query) -> Result<String, TlError> {
+    query_fenced_into(queries, options, &mut FencedAnswer::default())
+}
+
+/// Same as [query_fenced], accumulating the answer in the given
+/// [FencedAnswer], so that the caller knows, on timeout, whether
+/// something was received
+pub fn query_fenced_into(
+    queries: &str,
+    options: &Query,
+    answer: &mut FencedAnswer,
+) -> Result<String, TlError> {
+    let deadlines = Deadlines::new(options);
+    let res = if let Some(io) = &options.io {
+        exchange_with(&mut **io.lock(), queries, deadlines, answer)
+    } else {
+        let tty = send_queries(queries)?;
+        read_until(&tty, deadlines, !options.assume_raw, |bytes| {
+            match answer.feed(bytes) {
+                Some(answer) => Poll::Ready(Ok(answer)),
+                None => Poll::Pending,
+            }
+        })
+    };
+    if res.is_err() {
+        answer.keep_unfenced_input();
+    }
+    res
+}
+
+/// Send the queries followed by the fence with a custom transport, and
+/// return the answer before the fence
+fn exchange_with(
+    io: &mut dyn TerminalIo,
+    queries: &str,
+    deadlines: Deadlines,
+    answer: &mut FencedAnswer,
+) -> Result<String, TlError> {
+    io.write_query(format!("{queries}{FENCE}").as_bytes())?;
+    loop {
+        let deadline = deadlines.current(answer.is_started());
+        if Instant::now() >= deadline {
+            return Err(TlError::Timeout);
+        }
+        let bytes = io.read_reply(deadline).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => TlError::Disconnected,
+            _ => e.into(),
+        })?;
+        if let Some(answer) = answer.feed(&bytes) {
+            return Ok(answer);
+        }
+    }
+}
+
+/// Wait for the fd to be readable, return false on timeout
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn wait_for_input(fd: BorrowedFd<'_>, timeout: Duration) -> Result<bool, TlError> {
+    use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+    let timeout = PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX);
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    let n = poll(&mut fds, timeout).map_err(io::Error::from)?;
+    Ok(n > 0)
+}
+
+/// Wait for the fd to be readable, return false on timeout
+///
+/// On MacOS, we need to use `select` instead of `poll` because
+/// poll doesn't work with a tty.
+#[cfg(target_os = "macos")]
+pub(crate) fn wait_for_input(fd: BorrowedFd<'_>, timeout: Duration) -> Result<bool, TlError> {
+    use {
+        nix::sys::{
+            select::{select, FdSet},
+            time::TimeVal,
+        },
+        std::os::fd::AsRawFd,
+    };
+    let mut fd_set = FdSet::new();
+    fd_set.insert(fd);
+    let mut tv = TimeVal::new(timeout.as_secs() as _, timeout.subsec_micros() as _);
+    let n =
+        select(fd.as_raw_fd() + 1, &mut fd_set, None, None, &mut tv).map_err(io::Error::from)?;
+    Ok(n > 0)
+}
+
+#[test]
+fn test_check_termios() {
+    // a file which isn't (or isn't anymore) a terminal gives an error
+    let file = File::open("/dev/null").unwrap();
+    assert!(matches!(
+        check_termios(file.as_fd()),
+        Err(TlError::TerminalAttributes(_)),
+    ));
+    // and has no foreground process group
+    assert!(!is_foreground_of(file.as_fd()));
+}
+
+#[test]
+fn test_fenced_answer() {
+    let mut answer = FencedAnswer::default();
+    assert_eq!(answer.feed(b"\x1b]11;rgb:0000/"), None);
+    assert_eq!(answer.feed(b"0000/0000\x07\x1b["), None);
+    assert_eq!(
+        answer.feed(b"0n").as_deref(),
+        Some("\x1b]11;rgb:0000/0000/0000\x07"),
+    );
+    let mut answer = FencedAnswer::default();
+    assert_eq!(answer.feed(b"\x1b[0n").as_deref(), Some(""));
+    // the keys typed around the reply aren't lost when the query times
+    // out (the global pending input isn't checked, as other tests may
+    // use it concurrently)
+    let mut answer = FencedAnswer::default();
+    assert_eq!(answer.feed(b"\x1b]11;rgb:28"), None);
+    assert_eq!(answer.feed(b"28/2c2c/3434\x07x"), None);
+    assert_eq!(answer.unfenced_input(), "x");
+}
+
+#[test]
+fn test_split_fenced() {
+    assert_eq!(split_fenced(b"\x1b]11;rgb:0000/0000/0000\x07"), None);
+    assert_eq!(
+        split_fenced(b"q\x1b]11;rgb:2828/2c2c/3434\x07\x1b[0nj"),
+        Some((
+            "q\x1b]11;rgb:2828/2c2c/3434\x07".to_string(),
+            "qj".to_string()
+        )),
+    );
+    assert_eq!(
+        split_fenced(b"\x9d11;rgb:2828/2c2c/3434\x9c\x9b0n"),
+        Some((
+            "\x1b]11;rgb:2828/2c2c/3434\x1b\\".to_string(),
+            String::new()
+        )),
+    );
+    // on a retry, the late reply to the first attempt is ended by its
+    // fence, and the whole reply to the second one comes after
+    assert_eq!(
+        split_fenced(b"\x1b]11;rgb:2828/2c2c/3434\x07\x1b[0n\x1b]11;rgb:2828/2c2c/3434\x07\x1b[0n"),
+        Some(("\x1b]11;rgb:2828/2c2c/3434\x07".to_string(), String::new())),
+    );
+}
+
+#[test]
+fn test_deadlines() {
+    let options = Query::new()
+        .timeout(Duration::from_millis(500))
+        .barrier_timeout(Duration::from_millis(50));
+    let deadlines = Deadlines::new(&options);
+    assert!(deadlines.current(false) < deadlines.current(true));
+    // the barrier timeout can't extend the overall one
+    let options = Query::new().barrier_timeout(Duration::from_secs(10));
+    let deadlines = Deadlines::new(&options);
+    assert_eq!(deadlines.current(false), deadlines.current(true));
+}
+
+#[test]
+fn test_vtime() {
+    assert_eq!(vtime(Duration::from_millis(1)), 1);
+    assert_eq!(vtime(Duration::from_millis(100)), 1);
+    assert_eq!(vtime(Duration::from_millis(199)), 1);
+    assert_eq!(vtime(Duration::from_millis(250)), 2);
+    assert_eq!(vtime(Duration::from_secs(60)), 255);
+}
+
+#[test]
+fn test_short_barrier_timeout() {
+    use nix::{pty::openpty, sys::termios::cfmakeraw};
+    // nothing is ever written on this terminal
+    let pty = openpty(None, None).unwrap();
+    let tty = File::from(pty.slave);
+    let mut termios = tcgetattr(&tty).unwrap();
+    cfmakeraw(&mut termios);
+    tcsetattr(&tty, SetArg::TCSANOW, &termios).unwrap();
+    let options = Query::new()
+        .timeout(Duration::from_millis(500))
+        .barrier_timeout(Duration::from_millis(20));
+    let start = Instant::now();
+    let res: Result<(), _> = read_until(&tty, Deadlines::new(&options), true, |_| Poll::Pending);
+    assert!(matches!(res, Err(TlError::Timeout)));
+    // not rounded up to the 100ms of a VTIME step
+    assert!(start.elapsed() < Duration::from_millis(80));
+}
+
+#[test]
+fn test_wrap_for_multiplexer() {
+    env::with_env(&[("STY", Some("1234.pts-0.host"))], || {
+        assert_eq!(wrap_for_multiplexer("\x1b[5n", "xterm-256color"), "\x1b[5n");
+        assert_eq!(
+            wrap_for_multiplexer("\x1b[5n", "screen-256color"),
+            "\x1bP\x1b[5n\x1b\\",
+        );
+    });
+    // TERM says screen, but we're not in Screen
+    env::with_env(&[("STY", None)], || {
+        assert_eq!(
+            wrap_for_multiplexer("\x1b[5n", "screen-256color"),
+            "\x1b[5n"
+        );
+    });
+    env::with_env(
+        &[("STY", None), ("TMUX", Some("/tmp/tmux-1000/default,1,0"))],
+        || {
+            assert_eq!(
+                wrap_for_multiplexer("\x1b[5n", "screen-256color"),
+                "\x1b[5n"
+            );
+        },
+    );
+}
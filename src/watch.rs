@@ -0,0 +1,126 @@
+use {
+    crate::*,
+    std::{
+        ops::Deref,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc::{self, Receiver},
+            Arc,
+        },
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    },
+};
+
+/// Minimal change of luma for the polling watch to notify it, so that
+/// tiny differences between two readings of the same background don't
+/// count as changes
+const MIN_LUMA_CHANGE: f32 = 0.001;
+
+/// Longest sleep of the polling watch between two checks of whether
+/// it should stop
+const MAX_SLEEP: Duration = Duration::from_millis(100);
+
+/// The watching of the terminal's background started by [watch].
+///
+/// It dereferences to the receiver of the lumas. Dropping it stops the
+/// watching thread and waits for it to restore the terminal, so drop it
+/// before the program exits.
+#[derive(Debug)]
+pub struct ThemeWatch {
+    rx: Receiver<f32>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Deref for ThemeWatch {
+    type Target = Receiver<f32>;
+    fn deref(&self) -> &Receiver<f32> {
+        &self.rx
+    }
+}
+
+impl Drop for ThemeWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Watch the changes of the terminal's background, and return the
+/// watch, on which its luma is received, at start then after every change.
+///
+/// The watching is done on a new thread, which stops when the watch
+/// is dropped. When no thread can be started (eg on wasm), the luma is
+/// only received once.
+///
+/// When the terminal says it can notify the changes of its color scheme
+/// (checked with a DECRQM query of the private mode 2031, which Contour,
+/// kitty, Ghostty and a few others support), the background is queried
+/// again only when the terminal notifies a change. The terminal is then
+/// kept in raw mode while watching, and the thread reads stdin: the keys
+/// the user types are kept for [take_pending_input], which the application
+/// should call instead of reading stdin.
+///
+/// Otherwise, the luma is polled every `interval`, and sent only when
+/// it changed. As the terminal's answers come on stdin, the application
+/// shouldn't read stdin while watching.
+pub fn watch(interval: Duration) -> ThemeWatch {
+    let (tx, rx) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = Arc::clone(&stop);
+    let query = Query {
+        use_exported: false,
+        ..Query::new()
+    };
+    let thread_query = query.clone();
+    let thread_tx = tx.clone();
+    let thread = thread::Builder::new().spawn(move || {
+        let (stop, query, tx) = (thread_stop, thread_query, thread_tx);
+        #[cfg(unix)]
+        if xterm::supports_theme_notifications(&query)
+            && xterm::watch_theme_notifications(&query, &stop, |luma| tx.send(luma).is_ok()).is_ok()
+        {
+            return;
+        }
+        let mut last: Option<Color> = None;
+        while !stop.load(Ordering::Relaxed) {
+            if let Ok(color) = query.background_color() {
+                if last.is_none_or(|last| color.luma_delta(last) >= MIN_LUMA_CHANGE) {
+                    if tx.send(color.luma()).is_err() {
+                        return;
+                    }
+                    last = Some(color);
+                }
+            }
+            sleep_unless_stopped(interval, &stop);
+        }
+    });
+    let thread = match thread {
+        Ok(thread) => Some(thread),
+        Err(_) => {
+            // no thread can be started (eg on wasm): only the current
+            // luma is sent
+            if let Ok(luma) = query.luma() {
+                let _ = tx.send(luma);
+            }
+            None
+        }
+    };
+    ThemeWatch { rx, stop, thread }
+}
+
+/// Sleep for the given duration, waking up regularly to return early
+/// when `stop` is set
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) {
+    let end = Instant::now() + duration;
+    while !stop.load(Ordering::Relaxed) {
+        let remaining = end.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return;
+        }
+        thread::sleep(remaining.min(MAX_SLEEP));
+    }
+}
@@ -1,42 +1,546 @@
 use crate::*;
-use std::time::Duration;
-
-fn query(query: &str, timeout_ms: u64) -> Result<String, TlError> {
-    use crossterm::terminal::*;
-    let switch_to_raw = !is_raw_mode_enabled()?;
-    if switch_to_raw {
-        enable_raw_mode()?;
-    }
-    let res = xterm_query::query_osc(query, timeout_ms).map_err(|e| e.into());
-    if switch_to_raw {
-        disable_raw_mode()?;
-    }
-    res
-}
-
-/// Query the bg color, assuming the terminal is in raw mode,
-/// using the "dynamic colors" OSC escape sequence.
-pub fn query_bg_color(timeout: Duration) -> Result<Rgb, TlError> {
-    // we use the "dynamic colors" OSC escape sequence. It's sent with a ? for
-    // a query and normally answered by the terminal with a color.
-    // References:
-    // - https://stackoverflow.com/a/28334701/263525
-    // - https://invisible-island.net/xterm/ctlseqs/ctlseqs.html
-    let s = query("\x1b]11;?\x07", timeout.as_millis() as u64)?;
-    // The string we receive is like `"]11;rgb:<red>/<green>/<blue>\\"`
-    // where `<red>`, `<green>`, and `<blue>` are 4 hex digits.
-    // Most terminals don't support such precision so they fill the 4 digits
-    // by repeating their 2 digits precision.
-    // For example, supposing the background is in #38A4C9 (blue),
-    // then we receive `"\u{1b}]11;rgb:3838/a4a4/c9c9\u{1b}\\"`.
-    // We read only the most significant hex digits which are good enough
-    // in all cases.
-    match s.strip_prefix("]11;rgb:") {
-        Some(raw_color) if raw_color.len() >= 14 => Ok(Rgb::new(
-            u8::from_str_radix(&raw_color[0..2], 16)?,
-            u8::from_str_radix(&raw_color[5..7], 16)?,
-            u8::from_str_radix(&raw_color[10..12], 16)?,
-        )),
-        _ => Err(TlError::WrongFormat(s.to_string())),
+use std::{
+    fs::File,
+    os::fd::AsFd,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+/// Oldest VTE version (in the `$VTE_VERSION` format, eg `4000` for 0.40)
+/// we trust to answer the OSC 11 query. Older versions don't answer, so
+/// querying them would only make us wait for the timeout.
+const VTE_MIN_OSC_VERSION: u32 = 4000;
+
+/// Return the version of VTE (GNOME Terminal, Tilix, etc.), if we're
+/// running in a VTE based terminal
+fn vte_version() -> Option<u32> {
+    env::var("VTE_VERSION")?.parse().ok()
+}
+
+/// Oldest version of macOS's Terminal.app (in the `$TERM_PROGRAM_VERSION`
+/// format, its build number, eg `400` for the 2.8 of macOS 10.13) we trust
+/// to answer the OSC 11 query
+const APPLE_TERMINAL_MIN_OSC_VERSION: u32 = 400;
+
+/// Return the build number of Terminal.app, if we're running in it
+/// (`None` also when it doesn't tell its version)
+fn apple_terminal_version() -> Option<u32> {
+    if env::term_program().as_deref() != Some(env::APPLE_TERMINAL) {
+        return None;
+    }
+    env::var("TERM_PROGRAM_VERSION")?
+        .split('.')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Return the terminator of the OSC queries to send to Terminal.app,
+/// given its version, or `None` when it's too old to answer them
+fn apple_terminal_osc_terminator(version: Option<u32>) -> Option<&'static str> {
+    match version {
+        // it doesn't answer the ST terminated form, but always
+        // answers with a BEL
+        Some(version) if version >= APPLE_TERMINAL_MIN_OSC_VERSION => Some("\x07"),
+        _ => None,
+    }
+}
+
+/// Return the terminator of the OSC queries to send, depending on the
+/// VTE version, or `None` when the terminal is known not to answer them.
+fn osc_terminator(vte_version: Option<u32>) -> Option<&'static str> {
+    match vte_version {
+        Some(version) if version < VTE_MIN_OSC_VERSION => None,
+        // VTE answers with a ST terminator, we send the query the same way
+        Some(_) => Some("\x1b\\"),
+        None => Some("\x07"),
+    }
+}
+
+/// Return the terminator of the OSC queries to send, or an error
+/// if the terminal is known not to answer OSC color queries, or if
+/// the query shouldn't send them (see [Query::quiet] and [Query::skip_in_ci])
+fn check_osc_support(options: &Query) -> Result<&'static str, TlError> {
+    if options.io.is_some() {
+        // the env doesn't tell anything about the terminal behind
+        // a custom transport
+        return Ok("\x07");
+    }
+    if env::term_program().as_deref() == Some(env::EMACS) {
+        // Emacs terminals don't reliably handle the reply and the
+        // query may end as garbage in the buffer
+        return Err(TlError::Unsupported);
+    }
+    if options.quiet && env::is_osc_unsafe() {
+        return Err(TlError::Unsupported);
+    }
+    if env::is_color_disabled() {
+        // the user doesn't want colors, so there's no reason to
+        // risk a query
+        return Err(TlError::Unsupported);
+    }
+    if options.skip_in_ci && env::is_ci() {
+        // there's usually no real terminal, and the query could end
+        // in the captured output
+        return Err(TlError::Unsupported);
+    }
+    if env::term_program().as_deref() == Some(env::APPLE_TERMINAL) {
+        return apple_terminal_osc_terminator(apple_terminal_version()).ok_or(TlError::Unsupported);
+    }
+    osc_terminator(vte_version()).ok_or(TlError::Unsupported)
+}
+
+/// A non blocking query of the terminal's background color, for
+/// applications having their own event loop (mio, calloop, etc.)
+/// and which can't wait for the answer.
+///
+/// The application owns the registration of the tty and the timing:
+/// it calls [QueryState::start], watches the returned tty, and passes
+/// the bytes it reads to [QueryState::feed] until it gets a result or
+/// [QueryState::is_timed_out] says it should give up.
+///
+/// The terminal must be in raw mode during the whole query (the crate
+/// doesn't change the terminal mode here, and [QueryState::start] fails
+/// without sending anything when it's in canonical mode).
+///
+/// ```no_run
+/// use std::{io::Read, task::Poll, time::Instant};
+/// use terminal_light::{Query, QueryState};
+///
+/// let mut state = QueryState::new(&Query::new());
+/// let mut tty = state.start()?;
+/// // in a real application, the tty would be registered in the event loop
+/// let mut buffer = [0; 64];
+/// let bg = loop {
+///     let n = tty.read(&mut buffer)?;
+///     if let Poll::Ready(bg) = state.feed(&buffer[..n]) {
+///         break bg;
+///     }
+///     if state.is_timed_out(Instant::now()) {
+///         break Err(terminal_light::TlError::Timeout);
+///     }
+/// };
+/// # Ok::<(), terminal_light::TlError>(())
+/// ```
+#[derive(Debug)]
+pub struct QueryState {
+    /// The dynamic color: 10 for the foreground, 11 for the background,
+    /// 12 for the cursor
+    code: u8,
+    options: Query,
+    deadlines: Option<tty::Deadlines>,
+    answer: tty::FencedAnswer,
+}
+
+impl QueryState {
+    /// Prepare a query of the background color
+    pub fn new(options: &Query) -> Self {
+        Self::for_color(11, options)
+    }
+    fn for_color(code: u8, options: &Query) -> Self {
+        Self {
+            code,
+            options: options.clone(),
+            deadlines: None,
+            answer: tty::FencedAnswer::default(),
+        }
+    }
+    /// Write the query to stdout and return the tty on which the
+    /// answer will come, and from which the application must read.
+    ///
+    /// This fails with a query having its own [transport](Query::terminal_io),
+    /// as its answer wouldn't come on the tty.
+    pub fn start(&mut self) -> Result<File, TlError> {
+        if self.options.io.is_some() {
+            return Err(TlError::Unsupported);
+        }
+        // we use the "dynamic colors" OSC escape sequence. It's sent with a ? for
+        // a query and normally answered by the terminal with a color.
+        // References:
+        // - https://stackoverflow.com/a/28334701/263525
+        // - https://invisible-island.net/xterm/ctlseqs/ctlseqs.html
+        let terminator = check_osc_support(&self.options)?;
+        let tty = tty::send_queries(&color_query(self.code, terminator))?;
+        self.deadlines = Some(tty::Deadlines::new(&self.options));
+        Ok(tty)
+    }
+    /// Pass bytes read from the tty, and get the color when the
+    /// answer is complete
+    pub fn feed(&mut self, bytes: &[u8]) -> Poll<Result<Color, TlError>> {
+        match self.answer.feed(bytes) {
+            Some(answer) => Poll::Ready(
+                reply::parse_color_reply(self.code, &answer).map(|(rgb, _)| Color::Rgb(rgb)),
+            ),
+            None => Poll::Pending,
+        }
+    }
+    /// Return the time after which the answer shouldn't be waited for,
+    /// if the query was started.
+    ///
+    /// This time is sooner before the terminal starts answering, when
+    /// a [barrier timeout](Query::barrier_timeout) is set.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadlines
+            .map(|deadlines| deadlines.current(self.answer.is_started()))
+    }
+    /// Tell whether the answer shouldn't be waited for anymore
+    pub fn is_timed_out(&self, now: Instant) -> bool {
+        self.deadline().is_some_and(|deadline| now >= deadline)
+    }
+}
+
+/// Return the query of a "dynamic color"
+fn color_query(code: u8, terminator: &str) -> String {
+    format!("\x1b]{code};?{terminator}")
+}
+
+/// Query the bg color, with its alpha (255 when opaque), using the
+/// "dynamic colors" OSC escape sequence
+pub fn query_bg_rgba(options: &Query) -> Result<(Rgb, u8), TlError> {
+    query_color_rgba(11, options)
+}
+
+/// Query the fg color using the "dynamic colors" OSC escape sequence
+pub fn query_fg_color(options: &Query) -> Result<Rgb, TlError> {
+    query_color(10, options)
+}
+
+/// Query the cursor color using the "dynamic colors" OSC escape sequence
+pub fn query_cursor_color(options: &Query) -> Result<Rgb, TlError> {
+    query_color(12, options)
+}
+
+/// Query a "dynamic color" (10 for the foreground, 11 for the background,
+/// 12 for the cursor)
+fn query_color(code: u8, options: &Query) -> Result<Rgb, TlError> {
+    query_color_rgba(code, options).map(|(rgb, _)| rgb)
+}
+
+/// Query a "dynamic color", with its alpha
+fn query_color_rgba(code: u8, options: &Query) -> Result<(Rgb, u8), TlError> {
+    reply::parse_color_reply(code, &query_color_answer(code, options)?)
+}
+
+/// Query the bg color, with the channels at the precision given by the
+/// terminal (usually 16 bits)
+pub fn query_bg_color_16bit(options: &Query) -> Result<(u16, u16, u16), TlError> {
+    reply::parse_color_reply_16bit(11, &query_color_answer(11, options)?)
+}
+
+/// Query a "dynamic color" and return the answer of the terminal,
+/// still to be parsed
+fn query_color_answer(code: u8, options: &Query) -> Result<String, TlError> {
+    let terminator = check_osc_support(options)?;
+    tty::with_raw_mode(options, || {
+        if options.da1_before_osc {
+            // we don't care about the answer, only that it was produced
+            let _ = tty::query_fenced("\x1b[c", options);
+        }
+        let query = color_query(code, terminator);
+        let mut retries = options.retries;
+        loop {
+            let mut answer = tty::FencedAnswer::default();
+            let res = tty::query_fenced_into(&query, options, &mut answer);
+            // When the answer to the fence came without the color, the query
+            // isn't supported. But when nothing came, both may have been lost
+            // on the way, so we try again.
+            if matches!(res, Err(TlError::Timeout)) && !answer.is_started() && retries > 0 {
+                retries -= 1;
+                continue;
+            }
+            return res;
+        }
+    })
+}
+
+/// Tell whether the terminal can notify the changes of its color scheme
+/// (private mode 2031, introduced by Contour and implemented by a few other
+/// terminals), by asking it whether it knows this mode with a DECRQM query.
+pub fn supports_theme_notifications(options: &Query) -> bool {
+    if check_osc_support(options).is_err() {
+        return false;
+    }
+    tty::with_raw_mode(options, || tty::query_fenced("\x1b[?2031$p", options))
+        .is_ok_and(|answer| parse_mode_2031_report(&answer))
+}
+
+/// Parse the answer to the DECRQM query of mode 2031, which is like
+/// `CSI ? 2031 ; <state> $ y`, the state being 1 (set) or 2 (reset) when
+/// the terminal knows the mode
+fn parse_mode_2031_report(answer: &str) -> bool {
+    answer.contains("[?2031;1$y") || answer.contains("[?2031;2$y")
+}
+
+/// How often the watching of the notifications checks whether
+/// it should stop
+const WATCH_WAKEUP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Enable the color scheme update notifications, then give the luma of
+/// the background to `on_luma`, at start then after every notified change,
+/// until it returns false or `stop` is set.
+///
+/// The tty is read and kept in raw mode all along. What's read and isn't
+/// a notification (the keys typed by the user) is kept for
+/// [take_pending_input].
+///
+/// This isn't supported with a query having its own [transport](Query::terminal_io).
+pub fn watch_theme_notifications<F>(
+    options: &Query,
+    stop: &AtomicBool,
+    mut on_luma: F,
+) -> Result<(), TlError>
+where
+    F: FnMut(f32) -> bool,
+{
+    if options.io.is_some() {
+        return Err(TlError::Unsupported);
+    }
+    tty::with_raw_mode(options, || {
+        let tty = tty::send("\x1b[?2031h")?;
+        let mut notifications = ThemeNotifications::default();
+        let res = (|| {
+            if !on_luma(query_bg_rgba(options)?.0.luma()) {
+                return Ok(());
+            }
+            let mut buffer = [0; 64];
+            while !stop.load(Ordering::Relaxed) {
+                if !tty::wait_for_input(tty.as_fd(), WATCH_WAKEUP_INTERVAL)? {
+                    notifications.flush();
+                    tty::keep_pending_input(&notifications.take_input());
+                    continue;
+                }
+                let bytes_read = match tty::read_tty(&tty, &mut buffer)? {
+                    0 => return Err(TlError::Disconnected),
+                    n => n,
+                };
+                let notified = notifications.feed(&buffer[..bytes_read]);
+                tty::keep_pending_input(&notifications.take_input());
+                if notified && !on_luma(query_bg_rgba(options)?.0.luma()) {
+                    return Ok(());
+                }
+            }
+            Ok(())
+        })();
+        notifications.flush();
+        tty::keep_pending_input(&notifications.take_input());
+        tty::send("\x1b[?2031l")?;
+        res
+    })
+}
+
+/// Detector of the color scheme update notifications in the bytes read
+/// from the tty, each notification being like `CSI ? 997 ; <1 for dark,
+/// 2 for light> n`, the other bytes being kept as user input
+#[derive(Debug, Default)]
+struct ThemeNotifications {
+    /// The received bytes not yet analyzed, ending with an escape
+    /// sequence which may be incomplete
+    pending: Vec<u8>,
+    /// The received bytes which aren't notifications
+    input: Vec<u8>,
+}
+
+impl ThemeNotifications {
+    const INTRODUCER: &'static [u8] = b"\x1b[?997;";
+    /// Add received bytes, and tell whether they completed a notification
+    fn feed(&mut self, bytes: &[u8]) -> bool {
+        self.pending.extend_from_slice(bytes);
+        // an escape sequence may be split between two reads
+        let complete = match self.pending.iter().rposition(|&b| b == 0x1b) {
+            Some(esc) if !is_complete_sequence(&self.pending[esc..]) => esc,
+            _ => self.pending.len(),
+        };
+        let bytes: Vec<u8> = self.pending.drain(..complete).collect();
+        self.analyze(&bytes)
+    }
+    /// Analyze the pending bytes, even if they end with an incomplete
+    /// sequence (the user may have just hit Escape)
+    fn flush(&mut self) -> bool {
+        let bytes = std::mem::take(&mut self.pending);
+        self.analyze(&bytes)
+    }
+    fn analyze(&mut self, bytes: &[u8]) -> bool {
+        let found = bytes
+            .windows(Self::INTRODUCER.len())
+            .any(|w| w == Self::INTRODUCER);
+        let input = reply::strip_replies(&reply::decode(bytes));
+        self.input.extend_from_slice(input.as_bytes());
+        found
+    }
+    /// Return, and forget, the received bytes which aren't notifications
+    fn take_input(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.input)
+    }
+}
+
+/// Tell whether the bytes, starting with ESC, make a complete CSI
+/// or OSC sequence (other sequences are considered complete)
+fn is_complete_sequence(sequence: &[u8]) -> bool {
+    match sequence.get(1) {
+        None => false,
+        Some(b'[') => sequence[2..].iter().any(|b| (0x40..=0x7e).contains(b)),
+        Some(b']') => sequence.contains(&0x07) || sequence[2..].windows(2).any(|w| w == b"\x1b\\"),
+        Some(_) => true,
+    }
+}
+
+/// Query entries of the terminal's palette, with only one round trip.
+///
+/// The returned colors are in the order of the requested indices, with
+/// the entries the terminal didn't answer being absent.
+pub fn query_palette_colors(indices: &[u8], options: &Query) -> Result<Vec<(u8, Rgb)>, TlError> {
+    // The palette is queried with an OSC 4 sequence per index, like
+    // `ESC]4;<index>;?BEL`
+    let terminator = check_osc_support(options)?;
+    let queries: String = indices
+        .iter()
+        .map(|index| format!("\x1b]4;{index};?{terminator}"))
+        .collect();
+    let s = tty::with_raw_mode(options, || tty::query_fenced(&queries, options))?;
+    let replies = reply::parse_palette_replies(&s);
+    let mut colors = Vec::with_capacity(indices.len());
+    for &index in indices {
+        if colors.iter().any(|&(i, _)| i == index) {
+            continue;
+        }
+        if let Some(&(_, rgb)) = replies.iter().find(|&&(i, _)| i == index) {
+            colors.push((index, rgb));
+        }
+    }
+    Ok(colors)
+}
+
+/// Query the foreground, background and cursor colors, and the 16 colors
+/// palette, with only one round trip
+pub fn query_snapshot(options: &Query) -> Result<FullSnapshot, TlError> {
+    let terminator = check_osc_support(options)?;
+    let mut queries: String = [10, 11, 12]
+        .into_iter()
+        .map(|code| color_query(code, terminator))
+        .collect();
+    for index in 0..16 {
+        queries.push_str(&format!("\x1b]4;{index};?{terminator}"));
+    }
+    let s = tty::with_raw_mode(options, || tty::query_fenced(&queries, options))?;
+    let snapshot = FullSnapshot::from_answer(&s);
+    if snapshot.is_empty() {
+        return Err(TlError::NotAnswered);
+    }
+    Ok(snapshot)
+}
+
+#[test]
+fn test_parse_mode_2031_report() {
+    assert!(parse_mode_2031_report("\x1b[?2031;2$y"));
+    assert!(!parse_mode_2031_report("\x1b[?2031;0$y"));
+    assert!(!parse_mode_2031_report(""));
+}
+
+#[test]
+fn test_theme_notifications() {
+    let mut notifications = ThemeNotifications::default();
+    assert!(!notifications.feed(b"abc"));
+    assert!(notifications.feed(b"\x1b[?997;1n"));
+    // a notification split between two reads
+    assert!(!notifications.feed(b"x\x1b[?99"));
+    assert!(notifications.feed(b"7;2n"));
+    assert!(!notifications.feed(b"q\x1b[A"));
+    // a lone Escape is only given on flush
+    assert!(!notifications.feed(b"\x1b"));
+    assert_eq!(notifications.take_input(), b"abcxq\x1b[A");
+    assert!(!notifications.flush());
+    assert_eq!(notifications.take_input(), b"\x1b");
+}
+
+#[test]
+fn test_osc_terminator() {
+    assert_eq!(osc_terminator(None), Some("\x07"));
+    assert_eq!(osc_terminator(Some(3405)), None);
+    assert_eq!(osc_terminator(Some(7200)), Some("\x1b\\"));
+    assert_eq!(apple_terminal_osc_terminator(Some(453)), Some("\x07"));
+    assert_eq!(apple_terminal_osc_terminator(Some(343)), None);
+    assert_eq!(apple_terminal_osc_terminator(None), None);
+    env::with_env(
+        &[
+            ("INSIDE_EMACS", None),
+            ("TERM_PROGRAM", Some("Apple_Terminal")),
+            ("TERM_PROGRAM_VERSION", Some("440.2")),
+        ],
+        || assert_eq!(apple_terminal_version(), Some(440)),
+    );
+}
+
+#[test]
+fn test_terminal_io() {
+    use std::{sync::mpsc, time::Instant};
+    /// A terminal answering the bg color query with a canned reply
+    struct FakeTerminal(mpsc::Sender<Vec<u8>>);
+    impl TerminalIo for FakeTerminal {
+        fn write_query(&mut self, query: &[u8]) -> std::io::Result<()> {
+            self.0.send(query.to_vec()).unwrap();
+            Ok(())
+        }
+        fn read_reply(&mut self, _deadline: Instant) -> std::io::Result<Vec<u8>> {
+            Ok(b"\x1b]11;rgb:2828/2c2c/3434\x07\x1b[0n".to_vec())
+        }
+        fn enter_raw(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+        fn restore(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let (tx, rx) = mpsc::channel();
+    let query = Query::new().terminal_io(Box::new(FakeTerminal(tx)));
+    let (rgb, alpha) = query_bg_rgba(&query).unwrap();
+    assert_eq!(rgb, Rgb::new(0x28, 0x2c, 0x34));
+    assert_eq!(alpha, 255);
+    assert_eq!(rx.recv().unwrap(), b"\x1b]11;?\x07\x1b[5n");
+    // the queries which can only be done on the tty aren't sent there
+    assert!(matches!(
+        QueryState::new(&query).start(),
+        Err(TlError::Unsupported)
+    ));
+    let stop = AtomicBool::new(false);
+    assert!(matches!(
+        watch_theme_notifications(&query, &stop, |_| true),
+        Err(TlError::Unsupported)
+    ));
+}
+
+#[test]
+fn test_disconnected_terminal() {
+    /// A terminal hung up before answering
+    struct HungUpTerminal;
+    impl TerminalIo for HungUpTerminal {
+        fn write_query(&mut self, _query: &[u8]) -> std::io::Result<()> {
+            Ok(())
+        }
+        fn read_reply(&mut self, _deadline: Instant) -> std::io::Result<Vec<u8>> {
+            Err(std::io::ErrorKind::UnexpectedEof.into())
+        }
+        fn enter_raw(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+        fn restore(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
     }
+    let query = Query::new().terminal_io(Box::new(HungUpTerminal));
+    assert!(matches!(query_bg_rgba(&query), Err(TlError::Disconnected)));
+    // the env describes another terminal, so it's not used as fallback
+    env::with_env(
+        &[("COLORFGBG", Some("15;0")), ("TERM", Some("linux"))],
+        || {
+            assert!(matches!(
+                query.background_color(),
+                Err(TlError::Disconnected)
+            ));
+            assert!(matches!(
+                query.foreground_color(),
+                Err(TlError::Disconnected)
+            ));
+        },
+    );
 }